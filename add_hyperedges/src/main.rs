@@ -4,14 +4,22 @@ use std::env;
 use std::fs::File;
 use std::io::{BufReader, BufWriter};
 use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+mod pattern;
+mod rules;
+
+use pattern::{Pattern, PropertyFilter};
 
 // Existing structs
 #[derive(Serialize, Deserialize, Debug)]
 struct Node {
-    id: String,
+    #[serde(deserialize_with = "scalar_option")]
+    id: Option<String>,
     label: String,
     #[serde(rename = "type")]
     node_type: String,
+    #[serde(deserialize_with = "scalar_strings")]
     properties: HashMap<String, String>,
 }
 
@@ -20,9 +28,19 @@ struct Edge {
     source: String,
     target: String,
     relation_type: String,
+    #[serde(deserialize_with = "scalar_strings")]
     properties: HashMap<String, String>,
 }
 
+impl Node {
+    // How hyperedges list the node: its id, or its label (which edges
+    // reference too) when it has none, as for the nodes VT, pDNS and GeoIP
+    // enrichment write.
+    fn member(&self) -> String {
+        self.id.clone().filter(|id| !id.is_empty()).unwrap_or_else(|| self.label.clone())
+    }
+}
+
 // New hyperedge struct
 #[derive(Serialize, Deserialize, Debug)]
 struct Hyperedge {
@@ -41,6 +59,25 @@ struct Graph {
     hyperedges: Vec<Hyperedge>, // Make hyperedges optional during deserialization
 }
 
+// Graphs written by universal_graph_transformer have numeric (or null) ids;
+// they are read as strings here.
+fn scalar(value: Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s),
+        other => Some(other.to_string()),
+    }
+}
+
+fn scalar_option<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(scalar(Value::deserialize(deserializer)?))
+}
+
+fn scalar_strings<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<HashMap<String, String>, D::Error> {
+    let values: HashMap<String, Value> = HashMap::deserialize(deserializer)?;
+    Ok(values.into_iter().filter_map(|(k, v)| scalar(v).map(|v| (k, v))).collect())
+}
+
 fn load_json<T: DeserializeOwned>(filename: &str) -> Result<T, Box<dyn std::error::Error>> {
     let file = File::open(filename)?;
     let reader = BufReader::new(file);
@@ -55,95 +92,114 @@ fn save_json<T: Serialize>(data: &T, filename: &str) -> Result<(), Box<dyn std::
     Ok(())
 }
 
-fn match_property_value(value: &str, filter: &str) -> bool {
-    if filter.contains('*') {
-        let parts: Vec<&str> = filter.split('*').collect();
-        return parts.iter().all(|part| value.contains(part));
-    }
-    value == filter
-}
-
-fn add_hyperedges(graph: &mut Graph, filter_property: Option<String>) {
-    let mut node_prop_map: HashMap<String, Vec<String>> = HashMap::new();
-    let mut edge_prop_map: HashMap<String, Vec<String>> = HashMap::new();
+fn add_hyperedges(graph: &mut Graph, filter: &PropertyFilter) {
+    let mut node_prop_map: HashMap<(String, String), Vec<String>> = HashMap::new();
+    let mut edge_prop_map: HashMap<(String, String), Vec<String>> = HashMap::new();
 
     // Collecting node properties
     for node in &graph.nodes {
-        for (key, value) in &node.properties {
-            let property_id = format!("{}::{}", key, value);
-            node_prop_map.entry(property_id).or_insert_with(Vec::new).push(node.id.clone());
+        for (key, value) in node.properties.iter().filter(|(k, v)| filter.matches(k, v)) {
+            node_prop_map.entry((key.clone(), value.clone())).or_default().push(node.member());
         }
     }
 
     // Collecting edge properties
     for edge in &graph.edges {
-        for (key, value) in &edge.properties {
-            let property_id = format!("{}::{}", key, value);
-            edge_prop_map.entry(property_id).or_insert_with(Vec::new).push(edge.source.clone() + &edge.target);
+        for (key, value) in edge.properties.iter().filter(|(k, v)| filter.matches(k, v)) {
+            edge_prop_map.entry((key.clone(), value.clone())).or_default().push(edge.source.clone() + &edge.target);
         }
     }
 
     // Adding hyperedges based on node properties
-    for (property, nodes) in node_prop_map {
+    for ((key, value), nodes) in node_prop_map {
         if nodes.len() > 1 {
-            let parts: Vec<&str> = property.split("::").collect();
-            let key = parts[0];
-            let value = parts[1];
-            if filter_property.as_deref().map_or(true, |filter| match_property_value(value, filter)) {
-                graph.hyperedges.push(Hyperedge {
-                    label: value.to_string(),
-                    edge_type: key.to_string(),
-                    hypertype: "node_property".to_string(),
-                    value: nodes,
-                });
-            }
+            graph.hyperedges.push(Hyperedge {
+                label: value,
+                edge_type: key,
+                hypertype: "node_property".to_string(),
+                value: nodes,
+            });
         }
     }
 
     // Adding super edges based on edge properties
-    for (property, edges) in edge_prop_map {
+    for ((key, value), edges) in edge_prop_map {
         if edges.len() > 1 {
-            let parts: Vec<&str> = property.split("::").collect();
-            let key = parts[0];
-            let value = parts[1];
-            if filter_property.as_deref().map_or(true, |filter| match_property_value(value, filter)) {
-                graph.hyperedges.push(Hyperedge {
-                    label: value.to_string(),
-                    edge_type: key.to_string(),
-                    hypertype: "superedge".to_string(),
-                    value: edges,
-                });
-            }
+            graph.hyperedges.push(Hyperedge {
+                label: value,
+                edge_type: key,
+                hypertype: "superedge".to_string(),
+                value: edges,
+            });
         }
     }
 }
 
-fn transform_graph(filename: &str, filter_property: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+fn transform_graph(filename: &str, filter: &PropertyFilter) -> Result<(), Box<dyn std::error::Error>> {
+    let mut graph: Graph = load_json(filename)?;
+
+    add_hyperedges(&mut graph, filter);
+
+    save_json(&graph, "graph_with_hyperedges.json")?;
+
+    Ok(())
+}
+
+// Adds only the hyperedges described by a rules file (see rules.rs).
+fn transform_graph_with_rules(filename: &str, rules_file: &str) -> Result<(), Box<dyn std::error::Error>> {
     let mut graph: Graph = load_json(filename)?;
+    let rules = rules::load_rules(rules_file)?;
 
-    add_hyperedges(&mut graph, filter_property);
+    let hyperedges = rules::apply_rules(&graph, &rules)?;
+    println!("{} rules added {} hyperedges", rules.rule.len(), hyperedges.len());
+    graph.hyperedges.extend(hyperedges);
 
     save_json(&graph, "graph_with_hyperedges.json")?;
 
     Ok(())
 }
 
+const USAGE: &str =
+    "<filename> [value] [--key <pattern>] [--regex] [--ignore-case] | <filename> --rules <rules.toml|rules.yaml>";
+
+fn run(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let filename = &args[0];
+    let mut rules_file = None;
+    let mut key = None;
+    let mut value = None;
+    let (mut regex, mut ignore_case) = (false, false);
+
+    let mut args = args[1..].iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rules" => rules_file = Some(args.next().ok_or("--rules needs a rules file")?),
+            "--key" => key = Some(args.next().ok_or("--key needs a pattern")?),
+            "--regex" => regex = true,
+            "--ignore-case" | "-i" => ignore_case = true,
+            flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag).into()),
+            _ => value = Some(arg),
+        }
+    }
+
+    if let Some(rules_file) = rules_file {
+        return transform_graph_with_rules(filename, rules_file);
+    }
+    let filter = PropertyFilter {
+        key: key.map(|k| Pattern::parse(k, regex, ignore_case)).transpose()?,
+        value: value.map(|v| Pattern::parse(v, regex, ignore_case)).transpose()?,
+    };
+    transform_graph(filename, &filter)
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
 
     if args.len() < 2 {
-        eprintln!("Usage: {} <filename> [property_value]", args[0]);
+        eprintln!("Usage: {} {}", args[0], USAGE);
         std::process::exit(1);
     }
 
-    let filename = &args[1];
-    let filter_property = if args.len() == 3 {
-        Some(args[2].clone())
-    } else {
-        None
-    };
-
-    match transform_graph(filename, filter_property) {
+    match run(&args[1..]) {
         Ok(_) => println!("Transformation with hyperedges successful!"),
         Err(e) => eprintln!("An error occurred: {}", e),
     }
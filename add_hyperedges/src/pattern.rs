@@ -0,0 +1,96 @@
+use regex::{Regex, RegexBuilder};
+use std::error::Error;
+
+// A key or value filter. Globs match the whole string: `*` is any run of
+// characters (including none), `?` is one character and everything else is
+// literal, so `evil*com` matches `evil.example.com` but not `com.evil`.
+// Regexes are searched for unless anchored with ^ and $.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    regex: Regex,
+}
+
+impl Pattern {
+    pub fn glob(glob: &str, ignore_case: bool) -> Result<Pattern, Box<dyn Error>> {
+        let mut expression = String::from("^");
+        for c in glob.chars() {
+            match c {
+                '*' => expression.push_str(".*"),
+                '?' => expression.push('.'),
+                c => expression.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        expression.push('$');
+        Pattern::regex(&expression, ignore_case)
+    }
+
+    pub fn regex(expression: &str, ignore_case: bool) -> Result<Pattern, Box<dyn Error>> {
+        let regex = RegexBuilder::new(expression)
+            .case_insensitive(ignore_case)
+            .dot_matches_new_line(true)
+            .build()
+            .map_err(|e| format!("invalid pattern {}: {}", expression, e))?;
+        Ok(Pattern { regex })
+    }
+
+    // A regex when `regex` is set or the pattern is written as `re:<regex>`,
+    // a glob otherwise.
+    pub fn parse(pattern: &str, regex: bool, ignore_case: bool) -> Result<Pattern, Box<dyn Error>> {
+        match pattern.strip_prefix("re:") {
+            Some(expression) => Pattern::regex(expression, ignore_case),
+            None if regex => Pattern::regex(pattern, ignore_case),
+            None => Pattern::glob(pattern, ignore_case),
+        }
+    }
+
+    pub fn matches(&self, value: &str) -> bool {
+        self.regex.is_match(value)
+    }
+}
+
+// Which properties take part in grouping: those whose key and value match
+// the respective pattern, when set.
+#[derive(Debug, Clone, Default)]
+pub struct PropertyFilter {
+    pub key: Option<Pattern>,
+    pub value: Option<Pattern>,
+}
+
+impl PropertyFilter {
+    pub fn matches(&self, key: &str, value: &str) -> bool {
+        self.key.as_ref().is_none_or(|p| p.matches(key)) && self.value.as_ref().is_none_or(|p| p.matches(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patterns() {
+        let glob = Pattern::glob("evil*com", false).unwrap();
+        assert!(glob.matches("evil.example.com"));
+        assert!(glob.matches("evilcom"));
+        assert!(!glob.matches("com.evil"));
+        assert!(!glob.matches("www.evil.example.com"));
+        assert!(!glob.matches("EVIL.example.com"));
+        assert!(Pattern::glob("evil*com", true).unwrap().matches("EVIL.example.COM"));
+        assert!(Pattern::glob("10.0.0.?", false).unwrap().matches("10.0.0.7"));
+        assert!(!Pattern::glob("10.0.0.?", false).unwrap().matches("10.0.0.17"));
+        assert!(!Pattern::glob("a.b", false).unwrap().matches("axb"));
+        assert!(Pattern::glob("HTTP/1.1 503*", false).unwrap().matches("HTTP/1.1 503 Service Unavailable\nServer: x"));
+
+        let regex = Pattern::parse(r"re:^AS\d+$", false, true).unwrap();
+        assert!(regex.matches("as13335"));
+        assert!(!regex.matches("AS13335 Cloudflare"));
+        assert!(Pattern::parse("jarm", true, false).unwrap().matches("last_jarm"));
+        assert!(Pattern::parse("re:(", false, false).is_err());
+
+        let filter = PropertyFilter {
+            key: Some(Pattern::glob("*jarm*", true).unwrap()),
+            value: None,
+        };
+        assert!(filter.matches("JARM.hash", "2ad2ad"));
+        assert!(!filter.matches("ja3", "2ad2ad"));
+    }
+}
@@ -0,0 +1,253 @@
+use crate::pattern::{Pattern, PropertyFilter};
+use crate::{Graph, Hyperedge};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+
+// A rules file lists curated hyperedges to add, e.g. in TOML:
+//
+// [[rule]]
+// name = "same registrant email"
+// key = "registrant_email"
+// types = ["domain"]
+//
+// or in YAML under `rules:` with the same fields.
+#[derive(Deserialize, Debug, Default)]
+pub struct RuleFile {
+    #[serde(default, alias = "rules")]
+    pub rule: Vec<Rule>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    // Group nodes sharing a property value (hypertype "node_property").
+    #[default]
+    Nodes,
+    // Group edges sharing a property value (hypertype "superedge").
+    Edges,
+    Both,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Rule {
+    pub name: String,
+    // Property key glob ("*jarm*"). Elements are grouped on the value,
+    // whichever of the matching keys it is under.
+    pub key: String,
+    // Only group on values matching this glob.
+    #[serde(default)]
+    pub value: Option<String>,
+    // `key` and `value` are regexes rather than globs (a single pattern can
+    // also be written as "re:<regex>").
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default)]
+    pub ignore_case: bool,
+    #[serde(default)]
+    pub scope: Scope,
+    // Node types, or relation types for edges, the rule looks at. Empty is
+    // every type.
+    #[serde(default)]
+    pub types: Vec<String>,
+    #[serde(default = "default_min_size")]
+    pub min_size: usize,
+    #[serde(default)]
+    pub max_size: Option<usize>,
+    // Hyperedge label; "{name}", "{key}" and "{value}" are filled in.
+    // Defaults to the shared value.
+    #[serde(default)]
+    pub label: Option<String>,
+    // Hyperedge type, the rule's key by default.
+    #[serde(default, rename = "type")]
+    pub hyperedge_type: Option<String>,
+}
+
+fn default_min_size() -> usize {
+    2
+}
+
+// TOML or YAML by extension; anything else is tried as TOML, then YAML.
+pub fn load_rules(filename: &str) -> Result<RuleFile, Box<dyn Error>> {
+    let data = fs::read_to_string(filename)?;
+    let rules = if filename.ends_with(".toml") {
+        toml::from_str(&data)?
+    } else if filename.ends_with(".yaml") || filename.ends_with(".yml") {
+        serde_yaml::from_str(&data)?
+    } else {
+        match toml::from_str(&data) {
+            Ok(rules) => rules,
+            Err(_) => serde_yaml::from_str(&data)?,
+        }
+    };
+    Ok(rules)
+}
+
+impl Rule {
+    fn in_scope(&self, element_type: &str) -> bool {
+        self.types.is_empty() || self.types.iter().any(|t| t.eq_ignore_ascii_case(element_type))
+    }
+
+    fn filter(&self) -> Result<PropertyFilter, Box<dyn Error>> {
+        let pattern = |p: &str| Pattern::parse(p, self.regex, self.ignore_case);
+        Ok(PropertyFilter {
+            key: Some(pattern(&self.key)?),
+            value: self.value.as_deref().map(pattern).transpose()?,
+        })
+    }
+
+    fn emit(&self, hypertype: &str, groups: BTreeMap<String, Vec<String>>) -> Vec<Hyperedge> {
+        groups
+            .into_iter()
+            .filter(|(_, members)| members.len() >= self.min_size)
+            .filter(|(_, members)| self.max_size.is_none_or(|max| members.len() <= max))
+            .map(|(value, members)| Hyperedge {
+                label: match &self.label {
+                    Some(label) => {
+                        label.replace("{name}", &self.name).replace("{key}", &self.key).replace("{value}", &value)
+                    }
+                    None => value,
+                },
+                edge_type: self.hyperedge_type.clone().unwrap_or_else(|| self.key.clone()),
+                hypertype: hypertype.to_string(),
+                value: members,
+            })
+            .collect()
+    }
+}
+
+// The hyperedges the rules describe, rule by rule and ordered by value
+// within a rule. Members are listed once per group. Fails on the first rule
+// with an invalid pattern.
+pub fn apply_rules(graph: &Graph, rules: &RuleFile) -> Result<Vec<Hyperedge>, Box<dyn Error>> {
+    let mut hyperedges = Vec::new();
+    for rule in &rules.rule {
+        let filter = rule.filter().map_err(|e| format!("rule {}: {}", rule.name, e))?;
+        if rule.scope != Scope::Edges {
+            let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            for node in graph.nodes.iter().filter(|n| rule.in_scope(&n.node_type)) {
+                for (_, value) in node.properties.iter().filter(|(k, v)| filter.matches(k, v)) {
+                    let member = node.member();
+                    let members = groups.entry(value.clone()).or_default();
+                    if !members.contains(&member) {
+                        members.push(member);
+                    }
+                }
+            }
+            hyperedges.extend(rule.emit("node_property", groups));
+        }
+        if rule.scope != Scope::Nodes {
+            let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            for edge in graph.edges.iter().filter(|e| rule.in_scope(&e.relation_type)) {
+                for (_, value) in edge.properties.iter().filter(|(k, v)| filter.matches(k, v)) {
+                    let member = edge.source.clone() + &edge.target;
+                    let members = groups.entry(value.clone()).or_default();
+                    if !members.contains(&member) {
+                        members.push(member);
+                    }
+                }
+            }
+            hyperedges.extend(rule.emit("superedge", groups));
+        }
+    }
+    Ok(hyperedges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Edge, Node};
+    use std::collections::HashMap;
+
+    fn node(id: &str, node_type: &str, props: &[(&str, &str)]) -> Node {
+        Node {
+            id: Some(id.to_string()),
+            label: id.to_string(),
+            node_type: node_type.to_string(),
+            properties: props.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn test_apply_rules() {
+        let graph = Graph {
+            nodes: vec![
+                node("a.example", "domain", &[("registrant_email", "x@evil.example"), ("jarm", "2ad2ad")]),
+                node("b.example", "domain", &[("registrant_email", "x@evil.example")]),
+                node("c.example", "domain", &[("registrant_email", "y@evil.example")]),
+                node("192.0.2.10", "ip_address", &[("registrant_email", "x@evil.example"), ("last_jarm", "2ad2ad")]),
+            ],
+            edges: vec![Edge {
+                source: "a.example".to_string(),
+                target: "192.0.2.10".to_string(),
+                relation_type: "resolves_to".to_string(),
+                properties: HashMap::from([("jarm".to_string(), "2ad2ad".to_string())]),
+            }],
+            hyperedges: Vec::new(),
+        };
+        let toml_rules: RuleFile = toml::from_str(
+            r#"
+            [[rule]]
+            name = "same registrant email"
+            key = "registrant_email"
+            types = ["domain"]
+            label = "{name}: {value}"
+
+            [[rule]]
+            name = "same JARM"
+            key = "*JARM"
+            ignore_case = true
+            type = "jarm"
+            scope = "both"
+            max_size = 2
+            "#,
+        )
+        .unwrap();
+        let yaml_rules: RuleFile = serde_yaml::from_str(
+            "rules:
+  - name: same registrant email
+    key: registrant_email
+    types: [domain]
+    label: \"{name}: {value}\"
+",
+        )
+        .unwrap();
+
+        let hyperedges = apply_rules(&graph, &toml_rules).unwrap();
+        assert_eq!(hyperedges.len(), 2);
+        assert_eq!(hyperedges[0].label, "same registrant email: x@evil.example");
+        assert_eq!(hyperedges[0].value, vec!["a.example", "b.example"]);
+        // The JARM is grouped across "jarm" and "last_jarm"; the single
+        // edge carrying it is below the minimum size.
+        assert_eq!(hyperedges[1].edge_type, "jarm");
+        assert_eq!(hyperedges[1].hypertype, "node_property");
+        assert_eq!(hyperedges[1].value, vec!["a.example", "192.0.2.10"]);
+
+        let from_yaml = apply_rules(&graph, &yaml_rules).unwrap();
+        assert_eq!(from_yaml.len(), 1);
+        assert_eq!(from_yaml[0].value, hyperedges[0].value);
+
+        let invalid: RuleFile = toml::from_str("[[rule]]\nname = \"bad\"\nkey = \"re:(\"\n").unwrap();
+        assert!(apply_rules(&graph, &invalid).is_err());
+    }
+
+    #[test]
+    fn test_apply_rules_without_ids() {
+        // Enrichment writes "id": null; such nodes are listed by label.
+        let graph: Graph = serde_json::from_value(serde_json::json!({
+            "nodes": [
+                {"id": null, "label": "a.example", "type": "domain", "properties": {"id": null, "registrant_email": "x@evil.example"}},
+                {"id": null, "label": "b.example", "type": "domain", "properties": {"registrant_email": "x@evil.example"}},
+                {"id": 7, "label": "c.example", "type": "domain", "properties": {"registrant_email": "x@evil.example"}}
+            ],
+            "edges": []
+        }))
+        .unwrap();
+        let rules: RuleFile = toml::from_str("[[rule]]\nname = \"same registrant\"\nkey = \"registrant_email\"\n").unwrap();
+        let hyperedges = apply_rules(&graph, &rules).unwrap();
+        assert_eq!(hyperedges.len(), 1);
+        assert_eq!(hyperedges[0].value, vec!["a.example", "b.example", "7"]);
+        assert!(serde_json::to_string(&graph).unwrap().contains("\"id\":null"));
+    }
+}
@@ -5,6 +5,8 @@ use crate::types::ChatRequest;
 use crate::types::Message;
 use crate::types::ChatResponse;
 
+pub const MODEL: &str = "gpt-4-turbo";
+
 pub async fn call_openai_chat(
     system_prompt: &str,
     prompt: &str,
@@ -12,7 +14,7 @@ pub async fn call_openai_chat(
 ) -> Result<String, Box<dyn Error>> {
     // Create the request body
     let request_body = ChatRequest {
-        model: "gpt-4-turbo".to_string(),
+        model: MODEL.to_string(),
         messages: vec![
             Message {
                 role: "system".to_string(),
@@ -36,7 +38,7 @@ pub async fn call_openai_chat(
 
     if response.status().is_success() {
         let chat_response: ChatResponse = response.json().await?;
-        if let Some(choice) = chat_response.choices.get(0) {
+        if let Some(choice) = chat_response.choices.first() {
             Ok(choice.message.content.clone())
         } else {
             Err("No choices in response".into())
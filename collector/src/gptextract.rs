@@ -1,6 +1,8 @@
+use chrono::Utc;
+use serde_json::{json, Value};
 use std::error::Error;
 
-use crate::gptcall::call_openai_chat;
+use crate::gptcall::{call_openai_chat, MODEL};
 use crate::simparse::fetch_and_extract;
 use crate::my_secret::get_openai_key_var;
 
@@ -42,3 +44,28 @@ pub async fn information_extraction(input: &str, entities: Option<&[&str]>, prox
     Ok(response)
 }
 
+
+// Marks the nodes and edges of an extraction response as asserted by the LLM
+// call on `reference` (the page URL or "text" for pasted input), in the
+// provenance format of the universal graph.
+pub fn attribute_extraction(extraction: &mut Value, reference: &str) {
+    let retrieved_at = Utc::now().to_rfc3339();
+    for key in ["nodes", "edges"] {
+        let Some(items) = extraction.get_mut(key).and_then(Value::as_array_mut) else {
+            continue;
+        };
+        for item in items.iter_mut().filter_map(Value::as_object_mut) {
+            let properties: Vec<&String> = item
+                .keys()
+                .filter(|k| !matches!(k.as_str(), "value" | "type" | "from" | "to"))
+                .collect();
+            let provenance = json!({
+                "source": format!("llm:{}", MODEL),
+                "reference": reference,
+                "retrieved_at": retrieved_at,
+                "properties": properties,
+            });
+            item.insert("provenance".to_string(), json!([provenance]));
+        }
+    }
+}
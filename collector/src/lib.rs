@@ -5,9 +5,9 @@ use std::fs::File;
 use std::env;
 use std::io::prelude::*;
 use anyhow::Result;
-use tokio;
+use universal_graph_transformer::types::Tlp;
 
-use crate::gptcall::call_openai_chat;
+use crate::gptcall::{call_openai_chat, MODEL};
 use crate::my_secret::get_openai_key_var;
 
 mod googler;
@@ -15,9 +15,10 @@ mod simparse;
 mod gptcall;
 pub mod gptextract;
 mod my_secret;
+pub mod resolve;
 mod types;
 
-async fn process() -> Result<(), Box<dyn Error>> {
+pub async fn process() -> Result<(), Box<dyn Error>> {
     // Parse command-line arguments
     let args: Vec<String> = env::args().collect();
 
@@ -31,7 +32,7 @@ async fn process() -> Result<(), Box<dyn Error>> {
 
     println!("{}", query);
     // Step 1: Google Shallow
-    let search_results = googler::search_query(&query).await?;
+    let search_results = googler::search_query(query).await?;
     let parsed_results = googler::parse_google_results(&search_results);
 
     println!("{:#?}", parsed_results);
@@ -46,7 +47,6 @@ async fn process() -> Result<(), Box<dyn Error>> {
     let tasks: Vec<_> = links.iter().map(|link| {
         let url = link.as_str().expect("Expected a valid URL");
         let tags = tags.clone();
-        let proxy_url = proxy_url.clone();
     
         async move {
             match simparse::fetch_and_extract(url, tags, proxy_url).await {
@@ -59,7 +59,7 @@ async fn process() -> Result<(), Box<dyn Error>> {
     
                     let extraction_input = serde_json::to_string(&json_results).unwrap();
     
-                    match gptextract::information_extraction(&extraction_input, Some(&entities), proxy_url).await {
+                    match gptextract::information_extraction(&extraction_input, Some(entities), proxy_url).await {
                         Ok(extraction_response) => {
                             println!("Extracted Information: {}", extraction_response);
     
@@ -68,7 +68,8 @@ async fn process() -> Result<(), Box<dyn Error>> {
     
                             // Parse the extraction_response as JSON
                             match serde_json::from_str::<serde_json::Value>(&extraction_response) {
-                                Ok(extraction_json) => {
+                                Ok(mut extraction_json) => {
+                                    gptextract::attribute_extraction(&mut extraction_json, url);
                                     // Extract nodes and edges
                                     let nodes = extraction_json["nodes"].as_array().unwrap_or(&vec![]).clone();
                                     let edges = extraction_json["edges"].as_array().unwrap_or(&vec![]).clone();
@@ -114,9 +115,16 @@ async fn process() -> Result<(), Box<dyn Error>> {
         }
     }
 
+    // Merge name variants deterministically before asking GPT to clean up
+    let resolver = resolve::ResolverConfig::from_env()?;
+    for proposal in resolve::resolve_entities(&mut aggregated_results, &resolver) {
+        println!("Merged '{}' into '{}' ({}, {:.2})", proposal.merge, proposal.keep, proposal.reason, proposal.score);
+    }
+
     let constructed_prompt = format!(
         "clean up this node/edge set so that there are no duplicates and the naming conventions are standardized, 
         The user's original search query was '{}' and these nodes and edges represent the data returned. 
+        Keep the provenance list of every node and edge; when combining duplicates, concatenate their provenance lists. 
          \n\n{}",
         &query,
         aggregated_results
@@ -127,7 +135,11 @@ async fn process() -> Result<(), Box<dyn Error>> {
                                         &constructed_prompt, get_openai_key_var()).await?;
     
      // Parse the JSON string into a serde_json::Value
-    let v: Value = serde_json::from_str(&response)?;
+    let mut v: Value = serde_json::from_str(&response)?;
+    if v.is_object() {
+        let sources: Vec<&str> = links.iter().filter_map(Value::as_str).collect();
+        v["metadata"] = graph_metadata(query, &sources);
+    }
     
      // Serialize the Value back to a JSON string
     let results_json = serde_json::to_string(&v)?;
@@ -145,3 +157,21 @@ async fn process() -> Result<(), Box<dyn Error>> {
     
     Ok(())
 }
+
+// Header in the universal graph format: the search query names the graph and
+// the pages it was extracted from are its sources. GRAPH_TLP sets the TLP.
+fn graph_metadata(query: &str, sources: &[&str]) -> Value {
+    let mut metadata = serde_json::json!({
+        "name": query,
+        "description": format!("{} extraction of search results for '{}'", MODEL, query),
+        "created": chrono::Utc::now().to_rfc3339(),
+        "sources": sources,
+        "tool_version": concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")),
+    });
+    // Parsed like every other tool so the header always loads; unknown
+    // labels are warned about and left out.
+    if let Some(tlp) = Tlp::from_env() {
+        metadata["tlp"] = Value::String(tlp.to_string());
+    }
+    metadata
+}
@@ -0,0 +1,373 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+use universal_graph_transformer::indicator::refang;
+
+// Deterministic entity resolution for extraction output ({"nodes": [{value,
+// type, ...}], "edges": [{from, to, type, ...}]}): finds nodes that name the
+// same entity ("Gootloader", "GootLoader malware", "gootloader") and merges
+// them before the GPT hygiene pass.
+
+pub const DEFAULT_THRESHOLD: f64 = 0.85;
+
+// Words that describe what an entity is rather than which one it is.
+const DESCRIPTORS: &[&str] = &[
+    "the", "malware", "ransomware", "trojan", "backdoor", "botnet", "loader", "stealer", "rat", "family", "variant",
+    "strain", "group", "gang", "actor", "threat", "campaign", "apt", "inc", "ltd", "llc", "corp", "corporation",
+];
+
+// Types whose values are indicators: a one-character difference is a
+// different indicator, so they only merge when their values are equal apart
+// from case, defanging and a trailing dot.
+const EXACT_TYPES: &[&str] = &[
+    "ip", "ipv4", "ipv6", "domain", "hostname", "url", "uri", "hash", "md5", "sha1", "sha256", "email", "file", "filename",
+    "cve", "asn",
+];
+
+#[derive(Debug, Clone)]
+pub struct ResolverConfig {
+    // Minimum similarity (0 to 1) for two names to be merged.
+    pub threshold: f64,
+    // Normalized alias -> normalized canonical name, e.g. "sodinokibi" ->
+    // "revil".
+    pub aliases: HashMap<String, String>,
+    // Only compare nodes of the same type.
+    pub type_aware: bool,
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        ResolverConfig { threshold: DEFAULT_THRESHOLD, aliases: HashMap::new(), type_aware: true }
+    }
+}
+
+impl ResolverConfig {
+    // ENTITY_MATCH_THRESHOLD overrides the threshold and ENTITY_ALIASES names
+    // an alias table to load.
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let mut config = ResolverConfig::default();
+        if let Some(threshold) = env::var("ENTITY_MATCH_THRESHOLD").ok().filter(|v| !v.trim().is_empty()) {
+            config.threshold = threshold.trim().parse()?;
+        }
+        if let Some(path) = env::var("ENTITY_ALIASES").ok().filter(|v| !v.trim().is_empty()) {
+            config.add_aliases(&serde_json::from_str(&fs::read_to_string(path.trim())?)?);
+        }
+        Ok(config)
+    }
+
+    // Alias tables are JSON objects mapping a canonical name to its aliases:
+    // {"REvil": ["Sodinokibi", "Sodin"]}.
+    pub fn add_aliases(&mut self, table: &Value) {
+        let Some(table) = table.as_object() else {
+            return;
+        };
+        for (canonical, aliases) in table {
+            let canonical = normalize(canonical);
+            for alias in aliases.as_array().into_iter().flatten().filter_map(Value::as_str) {
+                self.aliases.insert(normalize(alias), canonical.clone());
+            }
+        }
+    }
+}
+
+// A proposed merge of the node `merge` into the node `keep`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MergeProposal {
+    pub keep: String,
+    pub merge: String,
+    #[serde(rename = "type")]
+    pub node_type: String,
+    pub score: f64,
+    // "normalized", "alias" or "similar".
+    pub reason: String,
+}
+
+// Lowercase words with punctuation and descriptor words removed:
+// "GootLoader malware" -> "gootloader". Names that are nothing but
+// descriptors keep them.
+pub fn normalize(name: &str) -> String {
+    let lowered = name.to_lowercase().replace("[.]", ".");
+    let tokens: Vec<&str> = lowered.split(|c: char| !c.is_alphanumeric()).filter(|t| !t.is_empty()).collect();
+    let named: Vec<&str> = tokens.iter().copied().filter(|t| !DESCRIPTORS.contains(t)).collect();
+    if named.is_empty() {
+        tokens.join(" ")
+    } else {
+        named.join(" ")
+    }
+}
+
+// Similarity of two normalized names: 1 when they are the same apart from
+// spacing ("cobalt strike", "cobaltstrike"), otherwise the better of token
+// overlap and edit distance. Names with different numbers ("apt28",
+// "apt29", "gootloader 3") never match.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let compact = |s: &str| s.replace(' ', "");
+    let (compact_a, compact_b) = (compact(a), compact(b));
+    if compact_a == compact_b {
+        return 1.0;
+    }
+    let digits = |s: &str| s.chars().filter(char::is_ascii_digit).collect::<String>();
+    if digits(a) != digits(b) {
+        return 0.0;
+    }
+    let tokens_a: Vec<&str> = a.split(' ').collect();
+    let tokens_b: Vec<&str> = b.split(' ').collect();
+    let shared = tokens_a.iter().filter(|t| tokens_b.contains(t)).count();
+    let union = tokens_a.len() + tokens_b.len() - shared;
+    let overlap = shared as f64 / union as f64;
+    overlap.max(strsim::normalized_levenshtein(&compact_a, &compact_b))
+}
+
+fn exact_type(node_type: &str) -> bool {
+    node_type.to_lowercase().split(|c: char| !c.is_alphanumeric()).any(|t| EXACT_TYPES.contains(&t))
+}
+
+// "Evil[.]Example." -> "evil.example". Punctuation is kept: "evil-example.com"
+// and "evil.example.com" are different domains.
+fn indicator_key(value: &str) -> String {
+    refang(value).to_lowercase().trim_end_matches('.').to_string()
+}
+
+// Nodes are taken in order and each one is compared with the names kept so
+// far in its block (its type, unless `type_aware` is off); the first node of
+// an entity is the one kept.
+pub fn propose_merges(extraction: &Value, config: &ResolverConfig) -> Vec<MergeProposal> {
+    let mut kept: HashMap<String, Vec<(String, String)>> = HashMap::new();
+    let mut proposals = Vec::new();
+
+    for node in extraction["nodes"].as_array().into_iter().flatten() {
+        let Some(value) = node["value"].as_str() else {
+            continue;
+        };
+        let node_type = node["type"].as_str().unwrap_or("").to_string();
+        let block = if config.type_aware { node_type.to_lowercase() } else { String::new() };
+        let exact = exact_type(&node_type);
+        let (name, aliased) = if exact {
+            (indicator_key(value), false)
+        } else {
+            let normalized = normalize(value);
+            let name = config.aliases.get(&normalized).cloned().unwrap_or_else(|| normalized.clone());
+            let aliased = name != normalized;
+            (name, aliased)
+        };
+        let candidates = kept.entry(block).or_default();
+
+        let mut best: Option<(f64, &String)> = None;
+        for (other_name, other_value) in candidates.iter() {
+            let score = if exact {
+                if *other_name == name { 1.0 } else { 0.0 }
+            } else {
+                similarity(&name, other_name)
+            };
+            if score >= config.threshold && best.is_none_or(|(best_score, _)| score > best_score) {
+                best = Some((score, other_value));
+            }
+        }
+
+        match best {
+            Some((_, keep)) if keep == value => {
+                // The same value twice, nothing to report.
+            }
+            Some((score, keep)) => {
+                let reason = if aliased {
+                    "alias"
+                } else if score >= 1.0 {
+                    "normalized"
+                } else {
+                    "similar"
+                };
+                proposals.push(MergeProposal {
+                    keep: keep.clone(),
+                    merge: value.to_string(),
+                    node_type,
+                    score: (score * 100.0).round() / 100.0,
+                    reason: reason.to_string(),
+                });
+            }
+            None => candidates.push((name, value.to_string())),
+        }
+    }
+    proposals
+}
+
+// Folds every merged node into the one it is merged with: edges are
+// rewritten to the kept value, the kept node lists the other names under
+// "aliases", provenance lists are concatenated and missing keys are copied
+// over. Duplicate nodes and edges that result are collapsed the same way.
+pub fn apply_merges(extraction: &mut Value, proposals: &[MergeProposal]) {
+    let renamed: HashMap<(&str, &str), &str> =
+        proposals.iter().map(|p| ((p.merge.as_str(), p.node_type.as_str()), p.keep.as_str())).collect();
+    let renamed_value: HashMap<&str, &str> = proposals.iter().map(|p| (p.merge.as_str(), p.keep.as_str())).collect();
+
+    if let Some(nodes) = extraction.get("nodes").and_then(Value::as_array).cloned() {
+        let mut merged: Vec<Value> = Vec::new();
+        let mut index: HashMap<(String, String), usize> = HashMap::new();
+        for mut node in nodes {
+            let value = node["value"].as_str().unwrap_or("").to_string();
+            let node_type = node["type"].as_str().unwrap_or("").to_string();
+            let keep = renamed.get(&(value.as_str(), node_type.as_str())).copied().unwrap_or(&value).to_string();
+            if keep != value {
+                node["value"] = Value::String(keep.clone());
+                add_alias(&mut node, &value);
+            }
+            match index.get(&(keep.clone(), node_type.clone())) {
+                Some(&i) => fold(&mut merged[i], node),
+                None => {
+                    index.insert((keep, node_type), merged.len());
+                    merged.push(node);
+                }
+            }
+        }
+        extraction["nodes"] = Value::Array(merged);
+    }
+
+    if let Some(edges) = extraction.get("edges").and_then(Value::as_array).cloned() {
+        let mut merged: Vec<Value> = Vec::new();
+        let mut index: HashMap<(String, String, String), usize> = HashMap::new();
+        for mut edge in edges {
+            for end in ["from", "to"] {
+                if let Some(keep) = edge[end].as_str().and_then(|v| renamed_value.get(v)) {
+                    edge[end] = Value::String(keep.to_string());
+                }
+            }
+            let key = (
+                edge["from"].as_str().unwrap_or("").to_string(),
+                edge["to"].as_str().unwrap_or("").to_string(),
+                edge["type"].as_str().unwrap_or("").to_string(),
+            );
+            match index.get(&key) {
+                Some(&i) => fold(&mut merged[i], edge),
+                None => {
+                    index.insert(key, merged.len());
+                    merged.push(edge);
+                }
+            }
+        }
+        extraction["edges"] = Value::Array(merged);
+    }
+}
+
+// Proposes merges and applies them, returning what was merged.
+pub fn resolve_entities(extraction: &mut Value, config: &ResolverConfig) -> Vec<MergeProposal> {
+    let proposals = propose_merges(extraction, config);
+    apply_merges(extraction, &proposals);
+    proposals
+}
+
+fn add_alias(node: &mut Value, alias: &str) {
+    let Some(node) = node.as_object_mut() else {
+        return;
+    };
+    let aliases = node.entry("aliases").or_insert_with(|| Value::Array(Vec::new()));
+    if let Some(aliases) = aliases.as_array_mut() {
+        if !aliases.iter().any(|a| a == alias) {
+            aliases.push(Value::String(alias.to_string()));
+        }
+    }
+}
+
+fn fold(existing: &mut Value, other: Value) {
+    let (Some(existing), Value::Object(other)) = (existing.as_object_mut(), other) else {
+        return;
+    };
+    for (key, value) in other {
+        match key.as_str() {
+            "provenance" | "aliases" => append(existing, &key, value),
+            _ => {
+                existing.entry(key).or_insert(value);
+            }
+        }
+    }
+}
+
+fn append(object: &mut Map<String, Value>, key: &str, values: Value) {
+    let list = object.entry(key).or_insert_with(|| Value::Array(Vec::new()));
+    if let (Some(list), Value::Array(values)) = (list.as_array_mut(), values) {
+        for value in values {
+            if !list.contains(&value) {
+                list.push(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_resolve_entities() {
+        let mut extraction = json!({
+            "nodes": [
+                {"value": "GootLoader", "type": "malware", "provenance": [{"source": "llm:a", "reference": "p1"}]},
+                {"value": "gootloader", "type": "malware", "provenance": [{"source": "llm:a", "reference": "p2"}]},
+                {"value": "Gootloader malware", "type": "malware"},
+                {"value": "GootLoader 3", "type": "malware"},
+                {"value": "GootKit", "type": "malware"},
+                {"value": "Cobalt Strike", "type": "tool"},
+                {"value": "CobaltStrike", "type": "tool"},
+                {"value": "Emotet", "type": "malware"},
+                {"value": "Emotett", "type": "malware"},
+                {"value": "REvil ransomware", "type": "malware"},
+                {"value": "Sodinokibi", "type": "malware"},
+                {"value": "evil.example", "type": "domain"},
+                {"value": "evil.exampl", "type": "domain"},
+                {"value": "Evil[.]Example.", "type": "domain"},
+                {"value": "apt.example.com", "type": "domain"},
+                {"value": "example.com", "type": "domain"},
+                {"value": "evil.example.com", "type": "domain"},
+                {"value": "evil-example.com", "type": "domain"},
+                {"value": "http://evil.example/", "type": "url"},
+                {"value": "http://evil.example/malware", "type": "url"},
+                {"value": "GootLoader", "type": "threat_actor"}
+            ],
+            "edges": [
+                {"from": "GootLoader", "to": "Cobalt Strike", "type": "deploys"},
+                {"from": "gootloader", "to": "CobaltStrike", "type": "deploys"},
+                {"from": "Gootloader malware", "to": "GootKit", "type": "deploys"}
+            ]
+        });
+        let mut config = ResolverConfig::default();
+        config.add_aliases(&json!({"REvil": ["Sodinokibi", "Sodin"]}));
+
+        let proposals = resolve_entities(&mut extraction, &config);
+        let merged: Vec<(&str, &str, &str)> =
+            proposals.iter().map(|p| (p.merge.as_str(), p.keep.as_str(), p.reason.as_str())).collect();
+        assert_eq!(
+            merged,
+            vec![
+                ("gootloader", "GootLoader", "normalized"),
+                ("Gootloader malware", "GootLoader", "normalized"),
+                ("CobaltStrike", "Cobalt Strike", "normalized"),
+                ("Emotett", "Emotet", "similar"),
+                ("Sodinokibi", "REvil ransomware", "alias"),
+                ("Evil[.]Example.", "evil.example", "normalized"),
+            ]
+        );
+        assert!(proposals[3].score >= DEFAULT_THRESHOLD && proposals[3].score < 1.0);
+
+        let nodes = extraction["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), 15);
+        assert_eq!(nodes[0]["aliases"], json!(["gootloader", "Gootloader malware"]));
+        assert_eq!(nodes[0]["provenance"].as_array().unwrap().len(), 2);
+        let edges = extraction["edges"].as_array().unwrap();
+        assert_eq!(edges.len(), 2);
+        assert_eq!(edges[1]["from"], "GootLoader");
+
+        let unchanged = propose_merges(&extraction, &config);
+        assert!(unchanged.is_empty());
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(normalize("GootLoader malware"), "gootloader");
+        assert_eq!(normalize("The Ransomware"), "the ransomware");
+        assert_eq!(similarity("apt28", "apt29"), 0.0);
+        assert_eq!(similarity("cobalt strike", "cobaltstrike"), 1.0);
+        assert!(similarity("gootloader", "gootkit") < DEFAULT_THRESHOLD);
+    }
+}
@@ -1,5 +1,4 @@
 use serde_derive::{Serialize, Deserialize};
-use serde_json;
 
 // googler
 #[derive(Deserialize, Debug)]
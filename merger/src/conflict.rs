@@ -0,0 +1,299 @@
+use crate::{Edge, Node, Provenance, UnplacedEdge};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+// What to do when both graphs have a different value for the same property
+// (or node type) of a merged node.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ConflictPolicy {
+    KeepFirst,
+    // The second graph overwrites the first.
+    #[default]
+    KeepLast,
+    // Keep every distinct value, joined with " | ". Node types cannot be
+    // combined, so the first one is kept.
+    KeepAll,
+    // The value whose source was retrieved most recently, per provenance.
+    // Values without a retrieval time lose; ties keep the last value.
+    Newest,
+    // The value from the best ranked source (earlier in the list wins, e.g.
+    // ["virustotal", "maltego", "llm"]). Unranked sources come last; ties
+    // keep the last value.
+    PreferSource(Vec<String>),
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    // "keep-first", "keep-last", "keep-all", "newest" or
+    // "prefer:virustotal,maltego,llm".
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        if let Some(ranking) = spec.strip_prefix("prefer:") {
+            let ranking = ranking.split(',').map(str::trim).filter(|s| !s.is_empty()).map(str::to_string).collect();
+            return Ok(ConflictPolicy::PreferSource(ranking));
+        }
+        match spec {
+            "keep-first" | "first" => Ok(ConflictPolicy::KeepFirst),
+            "keep-last" | "last" => Ok(ConflictPolicy::KeepLast),
+            "keep-all" | "all" => Ok(ConflictPolicy::KeepAll),
+            "newest" => Ok(ConflictPolicy::Newest),
+            _ => Err(format!("unknown conflict policy {}", spec)),
+        }
+    }
+}
+
+// One property that had different values in the merged graphs.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub node: String,
+    pub property: String,
+    pub kept: String,
+    pub overwritten: String,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct MergeReport {
+    pub conflicts: Vec<Conflict>,
+    pub unplaced_edges: Vec<UnplacedEdge>,
+}
+
+impl MergeReport {
+    // Adds the findings of a later step.
+    pub fn extend(&mut self, other: MergeReport) {
+        self.conflicts.extend(other.conflicts);
+        self.unplaced_edges.extend(other.unplaced_edges);
+    }
+}
+
+enum Winner {
+    Existing,
+    Incoming,
+    Both,
+}
+
+const MULTI_VALUE_SEPARATOR: &str = " | ";
+
+// Folds `node` into `existing`, resolving conflicting values with `policy`
+// and recording each one in `report`. Sources keep credit only for the
+// property values that survive.
+pub(crate) fn merge_node(existing: &mut Node, node: Node, policy: &ConflictPolicy, report: &mut MergeReport) {
+    let (lost_existing, lost_incoming) = merge_properties(
+        &mut existing.properties.other,
+        &existing.provenance,
+        &node.properties.other,
+        &node.provenance,
+        &existing.label,
+        policy,
+        report,
+    );
+
+    // Unknown or missing types never override a known one.
+    let known = |t: &str| !t.is_empty() && t != "unknown";
+    if known(&node.node_type) && node.node_type != existing.node_type {
+        if !known(&existing.node_type) {
+            existing.node_type = node.node_type.clone();
+        } else {
+            let incoming_wins = matches!(pick(&existing.provenance, &node.provenance, None, policy), Winner::Incoming);
+            let (kept, overwritten) = if incoming_wins {
+                (node.node_type.clone(), existing.node_type.clone())
+            } else {
+                (existing.node_type.clone(), node.node_type.clone())
+            };
+            existing.node_type = kept.clone();
+            report.conflicts.push(Conflict {
+                node: existing.label.clone(),
+                property: "type".to_string(),
+                kept,
+                overwritten,
+            });
+        }
+    }
+
+    if node.id.is_some() && (existing.id.is_none() || matches!(policy, ConflictPolicy::KeepLast)) {
+        existing.id = node.id;
+    }
+
+    widen(&mut existing.first_seen, node.first_seen, |a, b| a < b);
+    widen(&mut existing.last_seen, node.last_seen, |a, b| a > b);
+    existing.count = add_counts(existing.count, &existing.provenance, node.count, &node.provenance);
+    combine_provenance(&mut existing.provenance, node.provenance, &lost_existing, &lost_incoming);
+}
+
+// Folds a duplicate of `existing` (same source, target and relation) into
+// it. Observation counts combine as for nodes; `merged_from` counts the
+// input edges and the provenance entries list the sources that saw it.
+pub(crate) fn merge_edge(existing: &mut Edge, edge: Edge, policy: &ConflictPolicy, report: &mut MergeReport) {
+    let name = format!("{} -{}-> {}", existing.source, existing.relation_type, existing.target);
+    let (lost_existing, lost_incoming) = merge_properties(
+        &mut existing.properties.other,
+        &existing.provenance,
+        &edge.properties.other,
+        &edge.provenance,
+        &name,
+        policy,
+        report,
+    );
+    if existing.properties.id.is_none() {
+        existing.properties.id = edge.properties.id;
+    }
+    widen(&mut existing.first_seen, edge.first_seen, |a, b| a < b);
+    widen(&mut existing.last_seen, edge.last_seen, |a, b| a > b);
+    existing.count = add_counts(existing.count, &existing.provenance, edge.count, &edge.provenance);
+    existing.merged_from = Some(existing.merged_from.unwrap_or(1) + edge.merged_from.unwrap_or(1));
+    combine_provenance(&mut existing.provenance, edge.provenance, &lost_existing, &lost_incoming);
+}
+
+// Copies `incoming` into `existing`, returning the keys whose existing and
+// incoming values lost a conflict.
+fn merge_properties(
+    existing: &mut HashMap<String, String>,
+    existing_provenance: &[Provenance],
+    incoming: &HashMap<String, String>,
+    incoming_provenance: &[Provenance],
+    name: &str,
+    policy: &ConflictPolicy,
+    report: &mut MergeReport,
+) -> (Vec<String>, Vec<String>) {
+    let mut lost_existing: Vec<String> = Vec::new();
+    let mut lost_incoming: Vec<String> = Vec::new();
+
+    let mut properties: Vec<(&String, &String)> = incoming.iter().collect();
+    properties.sort();
+    for (key, value) in properties {
+        let Some(current) = existing.get(key).cloned() else {
+            existing.insert(key.clone(), value.clone());
+            continue;
+        };
+        let already_kept = *policy == ConflictPolicy::KeepAll && current.split(MULTI_VALUE_SEPARATOR).any(|v| v == value);
+        if current == *value || already_kept {
+            continue;
+        }
+        let (kept, overwritten) = match pick(existing_provenance, incoming_provenance, Some(key), policy) {
+            Winner::Existing => {
+                lost_incoming.push(key.clone());
+                (current, value.clone())
+            }
+            Winner::Incoming => {
+                lost_existing.push(key.clone());
+                existing.insert(key.clone(), value.clone());
+                (value.clone(), current)
+            }
+            Winner::Both => {
+                let joined = format!("{}{}{}", current, MULTI_VALUE_SEPARATOR, value);
+                existing.insert(key.clone(), joined.clone());
+                (joined, String::new())
+            }
+        };
+        report.conflicts.push(Conflict { node: name.to_string(), property: key.clone(), kept, overwritten });
+    }
+    (lost_existing, lost_incoming)
+}
+
+// Counts of separate observations add up; a missing count says nothing
+// about how often the other source saw it. When every retrieval behind
+// `incoming` is already credited on `existing` (the same investigation
+// merged twice) its count was already included.
+fn add_counts(
+    existing: Option<u64>,
+    existing_provenance: &[Provenance],
+    incoming: Option<u64>,
+    incoming_provenance: &[Provenance],
+) -> Option<u64> {
+    let seen = !incoming_provenance.is_empty()
+        && incoming_provenance.iter().all(|p| existing_provenance.iter().any(|e| same_retrieval(e, p)));
+    match (existing, incoming) {
+        (Some(a), Some(_)) if seen => Some(a),
+        (Some(a), Some(b)) => Some(a + b),
+        (a, b) => a.or(b),
+    }
+}
+
+fn same_retrieval(a: &Provenance, b: &Provenance) -> bool {
+    a.source == b.source && a.reference == b.reference && a.retrieved_at == b.retrieved_at
+}
+
+// The same retrieval seen twice (a graph merged with itself) is kept once.
+fn combine_provenance(
+    existing: &mut Vec<Provenance>,
+    incoming: Vec<Provenance>,
+    lost_existing: &[String],
+    lost_incoming: &[String],
+) {
+    for provenance in existing.iter_mut() {
+        provenance.properties.retain(|k| !lost_existing.contains(k));
+    }
+    for mut provenance in incoming {
+        provenance.properties.retain(|k| !lost_incoming.contains(k));
+        if !existing.contains(&provenance) {
+            existing.push(provenance);
+        }
+    }
+}
+
+// Replaces `current` with `other` if `extends` says it extends the
+// window. Timestamps that do not parse only fill an empty bound.
+fn widen(current: &mut Option<String>, other: Option<String>, extends: fn(DateTime<Utc>, DateTime<Utc>) -> bool) {
+    let Some(other) = other else { return };
+    let replace = match current.as_deref() {
+        None => true,
+        Some(current) => match (parse_time(&other), parse_time(current)) {
+            (Some(a), Some(b)) => extends(a, b),
+            _ => false,
+        },
+    };
+    if replace {
+        *current = Some(other);
+    }
+}
+
+fn pick(existing: &[Provenance], incoming: &[Provenance], key: Option<&str>, policy: &ConflictPolicy) -> Winner {
+    match policy {
+        ConflictPolicy::KeepFirst => Winner::Existing,
+        ConflictPolicy::KeepLast => Winner::Incoming,
+        ConflictPolicy::KeepAll if key.is_some() => Winner::Both,
+        ConflictPolicy::KeepAll => Winner::Existing,
+        ConflictPolicy::Newest => {
+            let newest = |provenance: &[Provenance]| credited(provenance, key).filter_map(retrieved_at).max();
+            if newest(existing) > newest(incoming) {
+                Winner::Existing
+            } else {
+                Winner::Incoming
+            }
+        }
+        ConflictPolicy::PreferSource(ranking) => {
+            let best = |provenance: &[Provenance]| {
+                credited(provenance, key).map(|p| rank(ranking, &p.source)).min().unwrap_or(ranking.len())
+            };
+            if best(existing) < best(incoming) {
+                Winner::Existing
+            } else {
+                Winner::Incoming
+            }
+        }
+    }
+}
+
+// The sources behind a property value, or behind the element as a whole.
+fn credited<'a>(provenance: &'a [Provenance], key: Option<&'a str>) -> impl Iterator<Item = &'a Provenance> {
+    provenance
+        .iter()
+        .filter(move |p| key.is_none_or(|key| p.properties.iter().any(|k| k == key)))
+}
+
+fn retrieved_at(provenance: &Provenance) -> Option<DateTime<Utc>> {
+    parse_time(&provenance.retrieved_at)
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value).ok().map(|t| t.with_timezone(&Utc))
+}
+
+// "llm" ranks "llm:gpt-4-turbo" too.
+fn rank(ranking: &[String], source: &str) -> usize {
+    ranking
+        .iter()
+        .position(|r| source == r || source.strip_prefix(r.as_str()).is_some_and(|rest| rest.starts_with(':')))
+        .unwrap_or(ranking.len())
+}
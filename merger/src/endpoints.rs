@@ -0,0 +1,70 @@
+use crate::{Edge, MergeOptions, Node};
+use serde::Serialize;
+use std::collections::HashMap;
+
+// An edge that could not be attached to the merged graph, with the
+// endpoints that did not match any node of its graph.
+#[derive(Serialize, Debug, Clone)]
+pub struct UnplacedEdge {
+    pub edge: Edge,
+    pub missing: Vec<String>,
+}
+
+// Finds the merged node an edge endpoint of one input graph refers to. Edges
+// may name their endpoints by label, by node id (GraphML exports use "n3"
+// for the node with id 3) or by a differently written form of the node's
+// identity ("Example.COM." for "example.com").
+// `T` is whatever the caller tells merged nodes apart by (an index into
+// the merged node list, an identity key).
+pub(crate) struct EndpointIndex<T> {
+    labels: HashMap<String, Option<T>>,
+    ids: HashMap<String, Option<T>>,
+    keys: HashMap<String, Option<T>>,
+}
+
+impl<T> Default for EndpointIndex<T> {
+    fn default() -> Self {
+        EndpointIndex { labels: HashMap::new(), ids: HashMap::new(), keys: HashMap::new() }
+    }
+}
+
+impl<T: Clone + PartialEq> EndpointIndex<T> {
+    // Records that `node` of this graph became the merged node `merged`.
+    pub(crate) fn add(&mut self, node: &Node, merged: &T, options: &MergeOptions) {
+        insert(&mut self.labels, node.label.clone(), merged);
+        for id in [node.id, node.properties.id].into_iter().flatten() {
+            insert(&mut self.ids, id.to_string(), merged);
+            insert(&mut self.ids, format!("n{}", id), merged);
+        }
+        insert(&mut self.keys, options.normalization.apply(&node.label), merged);
+        insert(&mut self.keys, options.key(node), merged);
+        if let Some(canonical_id) = node.properties.other.get("canonical_id") {
+            insert(&mut self.keys, canonical_id.clone(), merged);
+        }
+    }
+
+    // Label first, then id, then identity key. Ambiguous references (two
+    // different nodes called "Unnamed Node") fall through to the next way.
+    pub(crate) fn resolve(&self, endpoint: &str, options: &MergeOptions) -> Option<T> {
+        let normalized = options.normalization.apply(endpoint);
+        [
+            self.labels.get(endpoint),
+            self.ids.get(endpoint),
+            self.keys.get(endpoint),
+            self.keys.get(&normalized),
+        ]
+        .into_iter()
+        .flatten()
+        .find_map(|target| target.clone())
+    }
+}
+
+fn insert<T: Clone + PartialEq>(map: &mut HashMap<String, Option<T>>, reference: String, merged: &T) {
+    map.entry(reference)
+        .and_modify(|target| {
+            if target.as_ref() != Some(merged) {
+                *target = None;
+            }
+        })
+        .or_insert_with(|| Some(merged.clone()));
+}
@@ -0,0 +1,115 @@
+use crate::{MergeOptions, Node};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+// How nodes from different graphs are recognised as the same entity.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum IdentityKey {
+    // The normalized label.
+    #[default]
+    Label,
+    // Node type plus normalized label, so an ip and a note that happen to
+    // share a label stay apart.
+    TypeAndValue,
+    // The normalized value of a property (e.g. "sha256"). Nodes without it
+    // fall back to type and value.
+    Property(String),
+    // The `canonical_id` property set by upstream tools, falling back to
+    // type and value.
+    CanonicalId,
+}
+
+impl FromStr for IdentityKey {
+    type Err = String;
+
+    // "label", "type", "property:<name>" or "canonical".
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        if let Some(property) = spec.strip_prefix("property:") {
+            return Ok(IdentityKey::Property(property.to_string()));
+        }
+        match spec {
+            "label" => Ok(IdentityKey::Label),
+            "type" | "type+value" => Ok(IdentityKey::TypeAndValue),
+            "canonical" | "canonical_id" => Ok(IdentityKey::CanonicalId),
+            _ => Err(format!("unknown identity key {}", spec)),
+        }
+    }
+}
+
+// Rules applied to values before they are compared.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Normalization {
+    // Compare case-insensitively.
+    pub case: bool,
+    // "example.com." is "example.com".
+    pub trailing_dot: bool,
+    // IP addresses in canonical form ("2001:DB8:0::1" is "2001:db8::1").
+    pub ip: bool,
+}
+
+impl Default for Normalization {
+    fn default() -> Self {
+        Normalization { case: true, trailing_dot: true, ip: true }
+    }
+}
+
+impl Normalization {
+    pub fn none() -> Self {
+        Normalization { case: false, trailing_dot: false, ip: false }
+    }
+
+    pub fn apply(&self, value: &str) -> String {
+        let value = value.trim();
+        if self.ip {
+            if let Ok(ip) = value.parse::<IpAddr>() {
+                return ip.to_string();
+            }
+        }
+        let value = if self.trailing_dot { value.trim_end_matches('.') } else { value };
+        if self.case {
+            value.to_lowercase()
+        } else {
+            value.to_string()
+        }
+    }
+}
+
+impl FromStr for Normalization {
+    type Err = String;
+
+    // Comma-separated rules to enable ("case,trailing-dot,ip") or "none".
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut normalization = Normalization::none();
+        for rule in spec.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+            match rule {
+                "none" => {}
+                "case" => normalization.case = true,
+                "trailing-dot" | "trailing_dot" => normalization.trailing_dot = true,
+                "ip" => normalization.ip = true,
+                _ => return Err(format!("unknown normalization rule {}", rule)),
+            }
+        }
+        Ok(normalization)
+    }
+}
+
+impl MergeOptions {
+    // The key under which `node` is merged.
+    pub fn key(&self, node: &Node) -> String {
+        let type_and_value = || {
+            format!("{}:{}", node.node_type.to_lowercase(), self.normalization.apply(&node.label))
+        };
+        match &self.identity {
+            IdentityKey::Label => self.normalization.apply(&node.label),
+            IdentityKey::TypeAndValue => type_and_value(),
+            IdentityKey::Property(property) => match node.properties.other.get(property) {
+                Some(value) => format!("{}={}", property, self.normalization.apply(value)),
+                None => type_and_value(),
+            },
+            IdentityKey::CanonicalId => match node.properties.other.get("canonical_id") {
+                Some(id) => format!("canonical_id={}", id),
+                None => type_and_value(),
+            },
+        }
+    }
+}
@@ -1,147 +1,673 @@
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+use endpoints::EndpointIndex;
+use universal_graph_transformer::types::Tlp;
+
+pub mod conflict;
+pub mod endpoints;
+pub mod identity;
+pub mod setops;
+
+pub use conflict::{Conflict, ConflictPolicy, MergeReport};
+pub use endpoints::UnplacedEdge;
+pub use identity::{IdentityKey, Normalization};
+pub use setops::{
+    difference, difference_report, intersection, intersection_report, symmetric_difference, symmetric_difference_report,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Node {
     pub id: Option<i32>,
     pub label: String,
     #[serde(rename = "type")]
     pub node_type: String,
     pub properties: Properties,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_seen: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub provenance: Vec<Provenance>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+// `first_seen`/`last_seen` (RFC 3339) and `count` are the observation
+// window and count written by universal_graph_transformer (e.g. pDNS hits).
+// `merged_from` is how many input edges were collapsed into this one.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Edge {
     pub source: String,
     pub target: String,
     pub relation_type: String,
     pub properties: Properties,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_seen: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merged_from: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub provenance: Vec<Provenance>,
+}
+
+// Where a node or edge came from, as written by universal_graph_transformer.
+// `properties` lists the keys whose current value that source supplied.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Provenance {
+    pub source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    pub retrieved_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Properties {
     pub id: Option<i32>,
     #[serde(flatten)]
     pub other: HashMap<String, String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Graph {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<GraphMetadata>,
     pub nodes: Vec<Node>,
     pub edges: Vec<Edge>,
 }
 
+// Graph header as written by universal_graph_transformer; `tlp` is a label
+// such as "TLP:AMBER".
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct GraphMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tlp: Option<String>,
+}
+
+// The merged graph is described by the first graph, lists the sources of
+// both and is shared under the stricter TLP of the two. Unknown TLP labels
+// count as the strictest.
+fn merge_metadata(first: Option<GraphMetadata>, second: Option<GraphMetadata>) -> Option<GraphMetadata> {
+    let (mut merged, other) = match (first, second) {
+        (Some(first), Some(second)) => (first, second),
+        (first, second) => return first.or(second),
+    };
+    for source in other.sources {
+        if !merged.sources.contains(&source) {
+            merged.sources.push(source);
+        }
+    }
+    merged.name = merged.name.or(other.name);
+    merged.description = merged.description.or(other.description);
+    merged.created = merged.created.or(other.created);
+    merged.tool_version = merged.tool_version.or(other.tool_version);
+    // Labels are read as universal_graph_transformer reads them; an Err
+    // (unknown label) orders after every Ok.
+    let rank = |tlp: &String| tlp.parse::<Tlp>();
+    merged.tlp = match (merged.tlp, other.tlp) {
+        (Some(a), Some(b)) => Some(if rank(&b) > rank(&a) { b } else { a }),
+        (a, b) => a.or(b),
+    };
+    Some(merged)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptions {
+    pub identity: IdentityKey,
+    pub normalization: Normalization,
+    pub conflicts: ConflictPolicy,
+    // Keep every edge instead of collapsing identical ones.
+    pub multigraph: bool,
+}
+
 pub fn merge_graphs(graph1: Graph, graph2: Graph) -> Graph {
-    let mut node_map: HashMap<String, Node> = HashMap::new();
-    let mut merged_edges: Vec<Edge> = Vec::new();
+    merge_graphs_with(graph1, graph2, &MergeOptions::default())
+}
+
+pub fn merge_graphs_with(graph1: Graph, graph2: Graph, options: &MergeOptions) -> Graph {
+    merge_graphs_report(graph1, graph2, options).0
+}
 
-    let add_or_merge_node = |node: Node, map: &mut HashMap<String, Node>| {
-        map.entry(node.label.clone()).and_modify(|existing_node| {
-            // Merge properties
-            existing_node.properties.other.extend(node.properties.other.clone());
+// Merges two graphs, collapsing nodes with the same identity key (nodes of
+// the same graph only when they do not carry different ids) and resolving conflicting property values with the configured policy. The
+// merged node keeps the label it was first seen under and edges are
+// rewritten to it, whether they referred to it by label, id or identity.
+// Edges with the same source, target and relation are collapsed into one
+// carrying the combined count, window, properties and provenance, unless
+// `multigraph` is set. The report lists every conflicting value and the
+// edges whose endpoints are not nodes of their graph, which are left out of
+// the result.
+pub fn merge_graphs_report(graph1: Graph, graph2: Graph, options: &MergeOptions) -> (Graph, MergeReport) {
+    let mut report = MergeReport::default();
+    let metadata = merge_metadata(graph1.metadata, graph2.metadata);
+    let mut nodes: Vec<Node> = Vec::new();
+    // Identity key -> every merged node with that key.
+    let mut index: HashMap<String, Vec<usize>> = HashMap::new();
+    // Edges with the positions of their merged endpoints in `nodes`.
+    let mut merged_edges: Vec<(usize, usize, Edge)> = Vec::new();
+    let mut edge_index: HashMap<(usize, usize, String), usize> = HashMap::new();
 
-            // Update node type and id if necessary
-            if node.id.is_some() {
-                existing_node.id = node.id;
+    for graph in [(graph1.nodes, graph1.edges), (graph2.nodes, graph2.edges)] {
+        let (graph_nodes, graph_edges) = graph;
+        let mut endpoints = EndpointIndex::default();
+        // Merged node -> id of the nodes of this graph folded into it. Two
+        // nodes of one graph with different ids are different entities
+        // (Maltego's "Unnamed Node" AS entities) whatever their key says.
+        let mut claimed: HashMap<usize, Option<i32>> = HashMap::new();
+        for node in graph_nodes {
+            let key = options.key(&node);
+            let id = node.id.or(node.properties.id);
+            let free = |i: &usize| match (claimed.get(i), id) {
+                (Some(Some(other)), Some(id)) => *other == id,
+                _ => true,
+            };
+            match index.get(&key).and_then(|candidates| candidates.iter().copied().find(free)) {
+                Some(i) => {
+                    endpoints.add(&node, &i, options);
+                    let claim = claimed.entry(i).or_insert(id);
+                    *claim = claim.or(id);
+                    conflict::merge_node(&mut nodes[i], node, &options.conflicts, &mut report);
+                }
+                None => {
+                    let i = nodes.len();
+                    endpoints.add(&node, &i, options);
+                    claimed.insert(i, id);
+                    index.entry(key).or_default().push(i);
+                    nodes.push(node);
+                }
             }
-            if !node.node_type.is_empty() {
-                existing_node.node_type = node.node_type.clone();
+        }
+
+        for mut edge in graph_edges {
+            match (endpoints.resolve(&edge.source, options), endpoints.resolve(&edge.target, options)) {
+                (Some(source), Some(target)) => {
+                    edge.source = nodes[source].label.clone();
+                    edge.target = nodes[target].label.clone();
+                    let key = (source, target, edge.relation_type.clone());
+                    match edge_index.get(&key) {
+                        Some(&i) if !options.multigraph => {
+                            conflict::merge_edge(&mut merged_edges[i].2, edge, &options.conflicts, &mut report);
+                        }
+                        _ => {
+                            edge_index.insert(key, merged_edges.len());
+                            merged_edges.push((source, target, edge));
+                        }
+                    }
+                }
+                (source, target) => {
+                    let mut missing = Vec::new();
+                    if source.is_none() {
+                        missing.push(edge.source.clone());
+                    }
+                    if target.is_none() {
+                        missing.push(edge.target.clone());
+                    }
+                    report.unplaced_edges.push(UnplacedEdge { edge, missing });
+                }
             }
-        }).or_insert(node);
-    };
+        }
+    }
 
-    for node in graph1.nodes.into_iter().chain(graph2.nodes.into_iter()) {
-        add_or_merge_node(node, &mut node_map);
+    let handles = endpoint_handles(&nodes, options);
+    let edges = merged_edges
+        .into_iter()
+        .map(|(source, target, mut edge)| {
+            edge.source = handles[source].clone();
+            edge.target = handles[target].clone();
+            edge
+        })
+        .collect();
+    let graph = Graph { metadata, nodes, edges };
+    (graph, report)
+}
+
+// What edges of the merged graph call each node: its label, or its identity
+// key when other merged nodes have the same label (two "Unnamed Node" files
+// told apart by sha256), or "n{id}" when the key is shared too (nodes of one
+// graph kept apart by their ids). These resolve in later merges and set
+// operations run with the same options.
+fn endpoint_handles(nodes: &[Node], options: &MergeOptions) -> Vec<String> {
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    let mut keys: HashMap<String, usize> = HashMap::new();
+    for node in nodes {
+        *labels.entry(node.label.as_str()).or_default() += 1;
+        *keys.entry(options.key(node)).or_default() += 1;
+    }
+    nodes
+        .iter()
+        .map(|node| {
+            let key = options.key(node);
+            match (labels[node.label.as_str()], keys[&key], node.id.or(node.properties.id)) {
+                (1, _, _) => node.label.clone(),
+                (_, 1, _) | (_, _, None) => key,
+                (_, _, Some(id)) => format!("n{}", id),
+            }
+        })
+        .collect()
+}
+
+// Merges any number of graphs left to right, as `merge_graphs_report` does
+// for two, collecting one report over all of them.
+pub fn merge_all(graphs: Vec<Graph>, options: &MergeOptions) -> (Graph, MergeReport) {
+    let mut merged = Graph::default();
+    let mut report = MergeReport::default();
+    for graph in graphs {
+        let (next, step) = merge_graphs_report(merged, graph, options);
+        merged = next;
+        report.extend(step);
     }
+    (merged, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_keeps_provenance() {
+        let graph1: Graph = serde_json::from_value(json!({
+            "nodes": [{"id": null, "label": "a.example", "type": "domain",
+                       "properties": {"id": null, "owner": "alice", "note": "phish"},
+                       "provenance": [{"source": "maltego", "reference": "case.graphml",
+                                       "retrieved_at": "2024-05-01T00:00:00Z", "properties": ["note", "owner"]}]}],
+            "edges": []
+        }))
+        .unwrap();
+        let graph2: Graph = serde_json::from_value(json!({
+            "nodes": [{"id": null, "label": "a.example", "type": "domain",
+                       "properties": {"id": null, "owner": "bob"},
+                       "provenance": [{"source": "llm", "retrieved_at": "2024-05-02T00:00:00Z",
+                                       "confidence": 0.6, "properties": ["owner"]}]}],
+            "edges": []
+        }))
+        .unwrap();
 
-    let node_labels: HashSet<String> = node_map.keys().cloned().collect();
+        let merged = merge_graphs(graph1, graph2);
+        let node = &merged.nodes[0];
+        assert_eq!(node.provenance.len(), 2);
+        assert_eq!(node.provenance[0].properties, vec!["note"]);
+        assert_eq!(node.provenance[1].properties, vec!["owner"]);
+        assert_eq!(node.provenance[1].confidence, Some(0.6));
+        assert!(serde_json::to_string(&merged).unwrap().contains("\"provenance\""));
+    }
 
-    let mut update_edge = |edge: Edge| {
-        if node_labels.contains(&edge.source) && node_labels.contains(&edge.target) {
-            merged_edges.push(edge);
+    fn node(label: &str, node_type: &str, props: &[(&str, &str)]) -> Node {
+        Node {
+            id: None,
+            label: label.to_string(),
+            node_type: node_type.to_string(),
+            properties: Properties {
+                id: None,
+                other: props.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            },
+            ..Default::default()
         }
-    };
+    }
 
-    for edge in graph1.edges.into_iter().chain(graph2.edges.into_iter()) {
-        update_edge(edge);
+    fn edge(source: &str, target: &str, relation_type: &str) -> Edge {
+        Edge {
+            source: source.to_string(),
+            target: target.to_string(),
+            relation_type: relation_type.to_string(),
+            ..Default::default()
+        }
     }
 
-    Graph {
-        nodes: node_map.into_values().collect(),
-        edges: merged_edges,
+    fn graph(nodes: Vec<Node>, edges: Vec<Edge>) -> Graph {
+        Graph { metadata: None, nodes, edges }
     }
-}
 
-fn main() {
-    // Example usage
-    let graph1 = Graph {
-        nodes: vec![
-            Node {
-                id: Some(1),
-                label: "A".to_string(),
-                node_type: "Type1".to_string(),
-                properties: Properties {
-                    id: Some(1),
-                    other: [("key1".to_string(), "value1".to_string())].iter().cloned().collect(),
-                },
-            },
-            Node {
-                id: Some(2),
-                label: "B".to_string(),
-                node_type: "Type2".to_string(),
-                properties: Properties {
-                    id: Some(2),
-                    other: [("key2".to_string(), "value2".to_string())].iter().cloned().collect(),
-                },
-            },
-        ],
-        edges: vec![
-            Edge {
-                source: "A".to_string(),
-                target: "B".to_string(),
-                relation_type: "connects".to_string(),
-                properties: Properties {
-                    id: Some(1),
-                    other: HashMap::new(),
-                },
-            },
-        ],
-    };
+    #[test]
+    fn test_merge_normalizes_identity() {
+        let graph1 = graph(
+            vec![node("Example.COM.", "domain", &[]), node("2001:DB8:0:0::1", "ip", &[])],
+            vec![edge("Example.COM.", "2001:DB8:0:0::1", "resolves_to")],
+        );
+        let graph2 = graph(
+            vec![node("example.com", "domain", &[]), node("2001:db8::1", "ip", &[]), node("mx.example.com", "domain", &[])],
+            vec![edge("example.com", "mx.example.com", "has_mx")],
+        );
+        let merged = merge_graphs(graph1, graph2);
+        assert_eq!(merged.nodes.len(), 3);
+        assert_eq!(merged.edges.len(), 2);
+        assert!(merged.edges.iter().all(|e| e.source == "Example.COM."));
 
-    let graph2 = Graph {
-        nodes: vec![
-            Node {
-                id: Some(3),
-                label: "A".to_string(),
-                node_type: "Type1".to_string(),
-                properties: Properties {
-                    id: Some(3),
-                    other: [("key3".to_string(), "value3".to_string())].iter().cloned().collect(),
-                },
-            },
-            Node {
-                id: Some(4),
-                label: "C".to_string(),
-                node_type: "Type3".to_string(),
-                properties: Properties {
-                    id: Some(4),
-                    other: [("key4".to_string(), "value4".to_string())].iter().cloned().collect(),
-                },
-            },
-        ],
-        edges: vec![
-            Edge {
-                source: "A".to_string(),
-                target: "C".to_string(),
-                relation_type: "connects".to_string(),
-                properties: Properties {
-                    id: Some(2),
-                    other: HashMap::new(),
-                },
-            },
-        ],
-    };
+        let strict = MergeOptions { normalization: Normalization::none(), ..Default::default() };
+        let merged = merge_graphs_with(
+            graph(vec![node("Example.COM.", "domain", &[])], Vec::new()),
+            graph(vec![node("example.com", "domain", &[])], Vec::new()),
+            &strict,
+        );
+        assert_eq!(merged.nodes.len(), 2);
+    }
 
-    let merged_graph = merge_graphs(graph1, graph2);
-    println!("{:#?}", merged_graph);
+    #[test]
+    fn test_merge_identity_keys() {
+        let by_type = MergeOptions { identity: "type".parse().unwrap(), ..Default::default() };
+        let merged = merge_graphs_with(
+            graph(vec![node("Contacted IP", "note", &[])], Vec::new()),
+            graph(vec![node("Contacted IP", "ip", &[])], Vec::new()),
+            &by_type,
+        );
+        assert_eq!(merged.nodes.len(), 2);
+
+        let by_hash = MergeOptions { identity: "property:sha256".parse().unwrap(), ..Default::default() };
+        let merged = merge_graphs_with(
+            graph(
+                vec![node("Unnamed Node", "file", &[("sha256", "AB12")]), node("Unnamed Node", "file", &[("sha256", "cd34")])],
+                Vec::new(),
+            ),
+            graph(vec![node("dropper.exe", "file", &[("sha256", "ab12")])], Vec::new()),
+            &by_hash,
+        );
+        assert_eq!(merged.nodes.len(), 2);
+        assert_eq!(merged.nodes[0].label, "Unnamed Node");
+
+        let canonical = MergeOptions { identity: IdentityKey::CanonicalId, ..Default::default() };
+        let merged = merge_graphs_with(
+            graph(vec![node("APT-X", "actor", &[("canonical_id", "intrusion-set--1")])], Vec::new()),
+            graph(vec![node("Fancy X", "actor", &[("canonical_id", "intrusion-set--1")])], Vec::new()),
+            &canonical,
+        );
+        assert_eq!(merged.nodes.len(), 1);
+        assert!("colour".parse::<IdentityKey>().is_err());
+    }
+
+    fn sourced(mut node: Node, source: &str, retrieved_at: &str) -> Node {
+        node.provenance.push(Provenance {
+            source: source.to_string(),
+            reference: None,
+            retrieved_at: retrieved_at.to_string(),
+            confidence: None,
+            properties: node.properties.other.keys().cloned().collect(),
+        });
+        node
+    }
+
+    fn merge_with_policy(policy: &str) -> (Graph, MergeReport) {
+        let first = node("evil.example", "domain", &[("owner", "alice"), ("country", "NL")]);
+        let first = sourced(first, "virustotal", "2024-05-01T00:00:00Z");
+        let second = node("evil.example", "url", &[("owner", "bob"), ("country", "NL")]);
+        let second = sourced(second, "llm:gpt-4-turbo", "2024-06-01T00:00:00+02:00");
+        let options = MergeOptions { conflicts: policy.parse().unwrap(), ..Default::default() };
+        merge_graphs_report(graph(vec![first], Vec::new()), graph(vec![second], Vec::new()), &options)
+    }
+
+    #[test]
+    fn test_merge_conflict_policies() {
+        let owner = |graph: &Graph| graph.nodes[0].properties.other["owner"].clone();
+
+        let (merged, report) = merge_with_policy("keep-last");
+        assert_eq!(owner(&merged), "bob");
+        assert_eq!(merged.nodes[0].node_type, "url");
+        assert_eq!(report.conflicts.len(), 2);
+        assert_eq!(
+            report.conflicts[0],
+            Conflict { node: "evil.example".into(), property: "owner".into(), kept: "bob".into(), overwritten: "alice".into() }
+        );
+        assert_eq!(merged.nodes[0].provenance[0].properties, vec!["country"]);
+
+        let (merged, _) = merge_with_policy("keep-first");
+        assert_eq!(owner(&merged), "alice");
+        assert_eq!(merged.nodes[0].node_type, "domain");
+
+        let (merged, report) = merge_with_policy("keep-all");
+        assert_eq!(owner(&merged), "alice | bob");
+        assert_eq!(report.conflicts[0].kept, "alice | bob");
+
+        let (merged, _) = merge_with_policy("newest");
+        assert_eq!(owner(&merged), "bob");
+
+        let (merged, _) = merge_with_policy("prefer:virustotal,llm");
+        assert_eq!(owner(&merged), "alice");
+        assert_eq!(merged.nodes[0].node_type, "domain");
+        let (merged, _) = merge_with_policy("prefer:llm");
+        assert_eq!(owner(&merged), "bob");
+
+        assert!("loudest".parse::<ConflictPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_merge_remaps_edge_endpoints() {
+        let mut n1 = node("192.0.2.10", "ipv4-address", &[]);
+        n1.id = Some(1);
+        let mut n2 = node("evil.example", "domain", &[]);
+        n2.id = Some(2);
+        let maltego = graph(vec![n1, n2], vec![edge("n2", "n1", "linked_to"), edge("n2", "n7", "linked_to")]);
+        let vt = graph(
+            vec![node("Evil.Example", "domain", &[]), node("mx.evil.example", "domain", &[])],
+            vec![edge("EVIL.EXAMPLE.", "mx.evil.example", "has_mx")],
+        );
+
+        let (merged, report) = merge_graphs_report(maltego, vt, &MergeOptions::default());
+        assert_eq!(merged.nodes.len(), 3);
+        assert_eq!(merged.edges.len(), 2);
+        assert_eq!((merged.edges[0].source.as_str(), merged.edges[0].target.as_str()), ("evil.example", "192.0.2.10"));
+        assert_eq!(merged.edges[1].source, "evil.example");
+        assert_eq!(report.unplaced_edges.len(), 1);
+        assert_eq!(report.unplaced_edges[0].missing, vec!["n7"]);
+    }
+
+    #[test]
+    fn test_merge_keeps_same_label_nodes_apart() {
+        let by_hash = MergeOptions { identity: "property:sha256".parse().unwrap(), ..Default::default() };
+        let mut dropper = node("Unnamed Node", "file", &[("sha256", "AB12")]);
+        dropper.id = Some(1);
+        let mut loader = node("Unnamed Node", "file", &[("sha256", "cd34")]);
+        loader.id = Some(2);
+        let maltego = graph(
+            vec![dropper, loader, node("c2.example", "domain", &[])],
+            vec![edge("n1", "c2.example", "contacted"), edge("n2", "c2.example", "contacted")],
+        );
+        let vt = graph(
+            vec![node("dropper.exe", "file", &[("sha256", "ab12")]), node("c2.example", "domain", &[])],
+            vec![edge("dropper.exe", "c2.example", "contacted")],
+        );
+
+        let (merged, report) = merge_all(vec![maltego.clone(), vt], &by_hash);
+        assert!(report.unplaced_edges.is_empty());
+        assert_eq!(merged.nodes.len(), 3);
+        let endpoints: Vec<(&str, Option<u64>)> = merged.edges.iter().map(|e| (e.source.as_str(), e.merged_from)).collect();
+        assert_eq!(endpoints, vec![("sha256=ab12", Some(2)), ("sha256=cd34", None)]);
+
+        let shared = intersection(merged, maltego, &by_hash);
+        assert_eq!(shared.edges.len(), 2);
+    }
+
+    #[test]
+    fn test_merge_all_single_graph_unchanged() {
+        let unnamed = |id: i32, number: &str| {
+            let mut node = node("Unnamed Node", "maltego.AS", &[("as.number", number)]);
+            node.id = Some(id);
+            node
+        };
+        let mut host = node("c2.example", "domain", &[]);
+        host.id = Some(3);
+        let maltego = graph(
+            vec![unnamed(1, "64500"), unnamed(2, "64501"), host],
+            vec![edge("n3", "n1", "announced_by"), edge("n3", "n2", "announced_by")],
+        );
+
+        for identity in ["label", "type", "canonical"] {
+            let options = MergeOptions { identity: identity.parse().unwrap(), ..Default::default() };
+            let (merged, report) = merge_all(vec![maltego.clone()], &options);
+            assert_eq!(merged.nodes.len(), 3);
+            assert_eq!(merged.edges.len(), 2);
+            assert!(report.conflicts.is_empty() && report.unplaced_edges.is_empty());
+            let targets: Vec<&str> = merged.edges.iter().map(|e| e.target.as_str()).collect();
+            assert_eq!(targets, vec!["n1", "n2"]);
+
+            let (twice, report) = merge_all(vec![maltego.clone(), maltego.clone()], &options);
+            assert_eq!(twice.nodes.len(), 3);
+            assert_eq!(twice.edges.len(), 2);
+            assert!(report.conflicts.is_empty() && report.unplaced_edges.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_merge_collapses_duplicate_edges() {
+        let observed = |source: &str, first_seen: &str, last_seen: &str, count: Option<u64>| -> Graph {
+            let mut resolves = edge("evil.example", "192.0.2.10", "resolves_to");
+            resolves.first_seen = Some(first_seen.to_string());
+            resolves.last_seen = Some(last_seen.to_string());
+            resolves.count = count;
+            resolves.properties.other.insert("port".to_string(), "443".to_string());
+            resolves.provenance.push(Provenance {
+                source: source.to_string(),
+                reference: None,
+                retrieved_at: "2024-06-01T00:00:00Z".to_string(),
+                confidence: None,
+                properties: Vec::new(),
+            });
+            graph(vec![node("evil.example", "domain", &[]), node("192.0.2.10", "ipv4-address", &[])], vec![resolves])
+        };
+        let pdns = observed("pdns", "2021-01-01T00:00:00Z", "2021-03-01T00:00:00Z", Some(15));
+        let vt = observed("virustotal", "2020-06-01T00:00:00Z", "2021-02-01T00:00:00Z", None);
+
+        let (merged, report) = merge_graphs_report(pdns.clone(), vt.clone(), &MergeOptions::default());
+        assert_eq!(merged.edges.len(), 1);
+        assert!(report.conflicts.is_empty());
+        let resolves = &merged.edges[0];
+        assert_eq!((resolves.count, resolves.merged_from), (Some(15), Some(2)));
+        assert_eq!(resolves.first_seen.as_deref(), Some("2020-06-01T00:00:00Z"));
+        assert_eq!(resolves.last_seen.as_deref(), Some("2021-03-01T00:00:00Z"));
+        assert_eq!(resolves.properties.other["port"], "443");
+        let sources: Vec<&str> = resolves.provenance.iter().map(|p| p.source.as_str()).collect();
+        assert_eq!(sources, vec!["pdns", "virustotal"]);
+
+        // The same investigation merged twice is still 15 hits.
+        let same = merge_graphs(pdns.clone(), pdns.clone());
+        assert_eq!((same.edges[0].count, same.edges[0].merged_from), (Some(15), Some(2)));
+        assert_eq!(same.edges[0].provenance.len(), 1);
+
+        let mut next_month = observed("pdns", "2021-04-01T00:00:00Z", "2021-05-01T00:00:00Z", Some(5));
+        next_month.edges[0].provenance[0].retrieved_at = "2024-07-01T00:00:00Z".to_string();
+        let later = merge_graphs(pdns.clone(), next_month);
+        assert_eq!(later.edges[0].count, Some(20));
+
+        let options = MergeOptions { multigraph: true, ..Default::default() };
+        let (merged, _) = merge_graphs_report(pdns, vt, &options);
+        assert_eq!(merged.edges.len(), 2);
+        assert!(merged.edges.iter().all(|e| e.merged_from.is_none()));
+    }
+
+    #[test]
+    fn test_merge_metadata() {
+        let graph = |name: &str, tlp: &str, source: &str| -> Graph {
+            serde_json::from_value(json!({
+                "metadata": {"name": name, "tlp": tlp, "sources": [source]},
+                "nodes": [],
+                "edges": []
+            }))
+            .unwrap()
+        };
+        let merged = merge_graphs(graph("case", "TLP:GREEN", "a.graphml"), graph("vt", "TLP:AMBER", "virustotal"));
+        let metadata = merged.metadata.unwrap();
+        assert_eq!(metadata.name.as_deref(), Some("case"));
+        assert_eq!(metadata.tlp.as_deref(), Some("TLP:AMBER"));
+        assert_eq!(metadata.sources, vec!["a.graphml", "virustotal"]);
+
+        // Labels are read like universal_graph_transformer reads them and
+        // unknown ones count as the strictest.
+        let cases = [("white", "TLP:GREEN", "TLP:GREEN"), ("TLP:RED", "TLP:PURPLE", "TLP:PURPLE")];
+        for (first, second, stricter) in cases {
+            let tlp = merge_graphs(graph("case", first, "a.graphml"), graph("vt", second, "virustotal"))
+                .metadata
+                .and_then(|metadata| metadata.tlp);
+            assert_eq!(tlp.as_deref(), Some(stricter));
+        }
+    }
+
+    #[test]
+    fn test_merge_all() {
+        let first = graph(
+            vec![node("evil.example", "domain", &[("owner", "alice")]), node("192.0.2.10", "ipv4-address", &[])],
+            vec![edge("evil.example", "192.0.2.10", "resolves_to")],
+        );
+        let second = graph(
+            vec![node("Evil.Example", "domain", &[("owner", "bob")]), node("192.0.2.10", "ipv4-address", &[])],
+            vec![edge("Evil.Example", "192.0.2.10", "resolves_to"), edge("Evil.Example", "gone", "links_to")],
+        );
+        let third = graph(
+            vec![node("192.0.2.10", "ipv4-address", &[]), node("mx.evil.example", "domain", &[])],
+            vec![edge("192.0.2.10", "mx.evil.example", "hosts")],
+        );
+
+        let (merged, report) = merge_all(vec![first, second, third], &MergeOptions::default());
+        assert_eq!(merged.nodes.len(), 3);
+        assert_eq!(merged.edges.len(), 2);
+        assert_eq!(merged.edges[0].merged_from, Some(2));
+        assert_eq!(merged.nodes[0].properties.other["owner"], "bob");
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.unplaced_edges.len(), 1);
+
+        let (empty, report) = merge_all(Vec::new(), &MergeOptions::default());
+        assert!(empty.nodes.is_empty() && empty.metadata.is_none());
+        assert!(report.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let last_week = graph(
+            vec![
+                node("evil.example", "domain", &[("owner", "alice")]),
+                node("192.0.2.10", "ipv4-address", &[]),
+                node("old.example", "domain", &[]),
+            ],
+            vec![
+                edge("evil.example", "192.0.2.10", "resolves_to"),
+                edge("old.example", "192.0.2.10", "resolves_to"),
+                edge("old.example", "gone.example", "has_mx"),
+            ],
+        );
+        let this_week = graph(
+            vec![
+                node("EVIL.example.", "domain", &[("owner", "mallory")]),
+                node("192.0.2.10", "ipv4-address", &[]),
+                node("new.example", "domain", &[]),
+            ],
+            vec![edge("EVIL.example.", "192.0.2.10", "resolves_to"), edge("new.example", "192.0.2.10", "resolves_to")],
+        );
+        let options = MergeOptions::default();
+        let labels = |g: &Graph| g.nodes.iter().map(|n| n.label.clone()).collect::<Vec<_>>();
+
+        let shared = intersection(last_week.clone(), this_week.clone(), &options);
+        assert_eq!(labels(&shared), vec!["evil.example", "192.0.2.10"]);
+        assert_eq!(shared.edges.len(), 1);
+        assert_eq!(shared.nodes[0].properties.other["owner"], "mallory");
+
+        let new = difference(this_week.clone(), last_week.clone(), &options);
+        assert_eq!(labels(&new), vec!["192.0.2.10", "new.example"]);
+        assert_eq!((new.edges.len(), new.edges[0].source.as_str()), (1, "new.example"));
+
+        let changed = symmetric_difference(last_week.clone(), this_week.clone(), &options);
+        assert_eq!(labels(&changed), vec!["192.0.2.10", "old.example", "new.example"]);
+        assert_eq!(changed.edges.len(), 2);
+
+        let (_, report) = intersection_report(last_week.clone(), this_week.clone(), &options);
+        assert_eq!((report.conflicts.len(), report.unplaced_edges.len()), (1, 1));
+        assert_eq!(report.unplaced_edges[0].missing, vec!["gone.example"]);
+        let (_, report) = difference_report(this_week.clone(), last_week.clone(), &options);
+        assert!(report.unplaced_edges.is_empty());
+        let (_, report) = symmetric_difference_report(last_week, this_week, &options);
+        assert_eq!(report.unplaced_edges.len(), 1);
+    }
 }
@@ -0,0 +1,213 @@
+use merger::{
+    difference_report, intersection_report, merge_all, symmetric_difference_report, Conflict, Graph, MergeOptions,
+    MergeReport, UnplacedEdge,
+};
+use serde::Serialize;
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "[--identity label|type|property:<name>|canonical] [--normalize case,trailing-dot,ip|none] \
+[--conflicts keep-first|keep-last|keep-all|newest|prefer:<sources>] [--multigraph] \
+[--operation union|intersection|difference|symmetric-difference] [--output merged.json] <file|directory>...";
+
+#[derive(Serialize)]
+struct InputSummary {
+    path: String,
+    nodes: usize,
+    edges: usize,
+}
+
+// Written next to the merged graph as merged.summary.json.
+#[derive(Serialize)]
+struct MergeSummary {
+    inputs: Vec<InputSummary>,
+    skipped: Vec<String>,
+    nodes: usize,
+    edges: usize,
+    conflicts: Vec<Conflict>,
+    unplaced_edges: Vec<UnplacedEdge>,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("Usage: {} {}", args[0], USAGE);
+        process::exit(1);
+    }
+    if let Err(e) = run(&args[1..]) {
+        eprintln!("An error occurred: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut options = MergeOptions::default();
+    let mut output_path = "merged.json".to_string();
+    let mut operation = "union".to_string();
+    let mut inputs: Vec<String> = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--identity" => options.identity = value()?.parse()?,
+            "--normalize" => options.normalization = value()?.parse()?,
+            "--conflicts" => options.conflicts = value()?.parse()?,
+            "--multigraph" => options.multigraph = true,
+            "--operation" => operation = value()?,
+            "--output" | "-o" => output_path = value()?,
+            flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag).into()),
+            input => inputs.push(input.to_string()),
+        }
+    }
+    if inputs.is_empty() {
+        return Err("no input graphs given".into());
+    }
+
+    let (graphs, mut summary) = load_inputs(&inputs, Path::new(&output_path))?;
+    let (merged, report) = combine(&operation, graphs, &options)?;
+    summary.nodes = merged.nodes.len();
+    summary.edges = merged.edges.len();
+    summary.conflicts = report.conflicts;
+    summary.unplaced_edges = report.unplaced_edges;
+
+    save_json(&merged, &output_path)?;
+    let summary_path = summary_path(&output_path);
+    save_json(&summary, &summary_path)?;
+    println!(
+        "Wrote the {} of {} graphs to {}: {} nodes, {} edges, {} conflicts, {} unplaced edges (summary in {})",
+        operation,
+        summary.inputs.len(),
+        output_path,
+        summary.nodes,
+        summary.edges,
+        summary.conflicts.len(),
+        summary.unplaced_edges.len(),
+        summary_path
+    );
+    Ok(())
+}
+
+// Union merges every graph. Intersection and symmetric difference fold
+// left to right; difference is the first graph minus all the others. The
+// report covers every step, including merging the graphs subtracted.
+fn combine(
+    operation: &str,
+    graphs: Vec<Graph>,
+    options: &MergeOptions,
+) -> Result<(Graph, MergeReport), Box<dyn Error>> {
+    let fold = |graphs: Vec<Graph>, op: fn(Graph, Graph, &MergeOptions) -> (Graph, MergeReport)| {
+        let mut graphs = graphs.into_iter();
+        let mut combined = graphs.next().unwrap_or_default();
+        let mut report = MergeReport::default();
+        for graph in graphs {
+            let (next, step) = op(combined, graph, options);
+            combined = next;
+            report.extend(step);
+        }
+        (combined, report)
+    };
+    match operation {
+        "union" => Ok(merge_all(graphs, options)),
+        "intersection" => Ok(fold(graphs, intersection_report)),
+        "symmetric-difference" => Ok(fold(graphs, symmetric_difference_report)),
+        "difference" => {
+            let mut graphs = graphs.into_iter();
+            let first = graphs.next().unwrap_or_default();
+            let (rest, mut report) = merge_all(graphs.collect(), options);
+            let (difference, step) = difference_report(first, rest, options);
+            report.extend(step);
+            Ok((difference, report))
+        }
+        _ => Err(format!("unknown operation {}", operation).into()),
+    }
+}
+
+// Files are loaded as given. Directories contribute every .json file in
+// them (not recursing), except status reports, merge summaries and the
+// output itself; files that are not graphs are skipped with a warning.
+fn load_inputs(inputs: &[String], output_path: &Path) -> Result<(Vec<Graph>, MergeSummary), Box<dyn Error>> {
+    let mut graphs = Vec::new();
+    let mut summary = MergeSummary {
+        inputs: Vec::new(),
+        skipped: Vec::new(),
+        nodes: 0,
+        edges: 0,
+        conflicts: Vec::new(),
+        unplaced_edges: Vec::new(),
+    };
+
+    for input in inputs {
+        let path = Path::new(input);
+        if !path.is_dir() {
+            let graph = load_graph(path).map_err(|e| format!("{}: {}", input, e))?;
+            summary.inputs.push(describe(path, &graph));
+            graphs.push(graph);
+            continue;
+        }
+        let mut files: Vec<PathBuf> = fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| is_graph_file(p) && !same_file(p, output_path))
+            .collect();
+        files.sort();
+        for file in files {
+            match load_graph(&file) {
+                Ok(graph) => {
+                    summary.inputs.push(describe(&file, &graph));
+                    graphs.push(graph);
+                }
+                Err(e) => {
+                    eprintln!("Skipping {}: {}", file.display(), e);
+                    summary.skipped.push(file.display().to_string());
+                }
+            }
+        }
+    }
+    Ok((graphs, summary))
+}
+
+fn is_graph_file(path: &Path) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    path.is_file() && name.ends_with(".json") && !name.ends_with(".status.json") && !name.ends_with(".summary.json")
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn describe(path: &Path, graph: &Graph) -> InputSummary {
+    InputSummary {
+        path: path.display().to_string(),
+        nodes: graph.nodes.len(),
+        edges: graph.edges.len(),
+    }
+}
+
+fn load_graph(path: &Path) -> Result<Graph, Box<dyn Error>> {
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+    let graph = serde_json::from_reader(reader)?;
+    Ok(graph)
+}
+
+fn save_json<T: Serialize>(data: &T, filename: &str) -> Result<(), Box<dyn Error>> {
+    let file = File::create(filename)?;
+    let writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(writer, data)?;
+    Ok(())
+}
+
+// merged.json -> merged.summary.json
+fn summary_path(output_path: &str) -> String {
+    match output_path.strip_suffix(".json") {
+        Some(stem) => format!("{}.summary.json", stem),
+        None => format!("{}.summary.json", output_path),
+    }
+}
@@ -0,0 +1,132 @@
+use crate::endpoints::EndpointIndex;
+use crate::{merge_graphs_report, Graph, MergeOptions, MergeReport, UnplacedEdge};
+use std::collections::HashSet;
+
+type EdgeKey = (String, String, String);
+
+// Identity keys of a graph's nodes and of its edges (source key, target key,
+// relation). Edges whose endpoints are not nodes of the graph have the
+// missing endpoints instead.
+struct Keys {
+    nodes: HashSet<String>,
+    edges: Vec<Result<EdgeKey, Vec<String>>>,
+}
+
+impl Keys {
+    fn of(graph: &Graph, options: &MergeOptions) -> Keys {
+        let mut endpoints: EndpointIndex<String> = EndpointIndex::default();
+        let mut nodes = HashSet::new();
+        for node in &graph.nodes {
+            let key = options.key(node);
+            endpoints.add(node, &key, options);
+            nodes.insert(key);
+        }
+        let edges = graph
+            .edges
+            .iter()
+            .map(|edge| match (endpoints.resolve(&edge.source, options), endpoints.resolve(&edge.target, options)) {
+                (Some(source), Some(target)) => Ok((source, target, edge.relation_type.clone())),
+                (source, target) => {
+                    let mut missing = Vec::new();
+                    if source.is_none() {
+                        missing.push(edge.source.clone());
+                    }
+                    if target.is_none() {
+                        missing.push(edge.target.clone());
+                    }
+                    Err(missing)
+                }
+            })
+            .collect();
+        Keys { nodes, edges }
+    }
+
+    fn edge_set(&self) -> HashSet<&EdgeKey> {
+        self.edges.iter().flatten().collect()
+    }
+}
+
+// Keeps the nodes whose key passes `keep_node` and the edges whose key
+// passes `keep_edge`, plus the endpoints of kept edges. Edges without a key
+// are reported as unplaced.
+fn filter(
+    graph: Graph,
+    keys: &Keys,
+    options: &MergeOptions,
+    keep_node: impl Fn(&str) -> bool,
+    keep_edge: impl Fn(&EdgeKey) -> bool,
+) -> (Graph, MergeReport) {
+    let mut report = MergeReport::default();
+    let mut edges = Vec::new();
+    for (edge, key) in graph.edges.into_iter().zip(&keys.edges) {
+        match key {
+            Ok(key) if keep_edge(key) => edges.push((edge, key)),
+            Ok(_) => {}
+            Err(missing) => report.unplaced_edges.push(UnplacedEdge { edge, missing: missing.clone() }),
+        }
+    }
+    let endpoints: HashSet<&String> = edges.iter().flat_map(|(_, (source, target, _))| [source, target]).collect();
+    let nodes = graph
+        .nodes
+        .into_iter()
+        .filter(|node| {
+            let key = options.key(node);
+            keep_node(&key) || endpoints.contains(&key)
+        })
+        .collect();
+    let graph = Graph {
+        metadata: graph.metadata,
+        nodes,
+        edges: edges.into_iter().map(|(edge, _)| edge).collect(),
+    };
+    (graph, report)
+}
+
+// The nodes and edges present in both graphs ("which infrastructure do
+// these campaigns share?"), merged as `merge_graphs_with` would.
+pub fn intersection(graph1: Graph, graph2: Graph, options: &MergeOptions) -> Graph {
+    intersection_report(graph1, graph2, options).0
+}
+
+// `intersection`, with the conflicts between the shared nodes and the
+// edges of either graph that could not be placed.
+pub fn intersection_report(graph1: Graph, graph2: Graph, options: &MergeOptions) -> (Graph, MergeReport) {
+    let (keys1, keys2) = (Keys::of(&graph1, options), Keys::of(&graph2, options));
+    let (edges1, edges2) = (keys1.edge_set(), keys2.edge_set());
+    let (shared1, mut report) = filter(graph1, &keys1, options, |k| keys2.nodes.contains(k), |e| edges2.contains(e));
+    let (shared2, step) = filter(graph2, &keys2, options, |k| keys1.nodes.contains(k), |e| edges1.contains(e));
+    report.extend(step);
+    let (graph, step) = merge_graphs_report(shared1, shared2, options);
+    report.extend(step);
+    (graph, report)
+}
+
+// What `graph1` has that `graph2` does not ("what is new this week?"): its
+// nodes and edges missing from `graph2`. Nodes both graphs have are kept
+// when a new edge needs them as endpoints.
+pub fn difference(graph1: Graph, graph2: Graph, options: &MergeOptions) -> Graph {
+    difference_report(graph1, graph2, options).0
+}
+
+// `difference`, reporting the edges of `graph1` that could not be placed.
+pub fn difference_report(graph1: Graph, graph2: Graph, options: &MergeOptions) -> (Graph, MergeReport) {
+    let (keys1, keys2) = (Keys::of(&graph1, options), Keys::of(&graph2, options));
+    let edges2 = keys2.edge_set();
+    filter(graph1, &keys1, options, |k| !keys2.nodes.contains(k), |e| !edges2.contains(e))
+}
+
+// What only one of the graphs has: both differences, merged.
+pub fn symmetric_difference(graph1: Graph, graph2: Graph, options: &MergeOptions) -> Graph {
+    symmetric_difference_report(graph1, graph2, options).0
+}
+
+// `symmetric_difference`, with the reports of both differences and of
+// merging them.
+pub fn symmetric_difference_report(graph1: Graph, graph2: Graph, options: &MergeOptions) -> (Graph, MergeReport) {
+    let (only1, mut report) = difference_report(graph1.clone(), graph2.clone(), options);
+    let (only2, step) = difference_report(graph2, graph1, options);
+    report.extend(step);
+    let (graph, step) = merge_graphs_report(only1, only2, options);
+    report.extend(step);
+    (graph, report)
+}
@@ -0,0 +1,152 @@
+use crate::enrich::{enrich_graph, Enricher, EnrichmentStatus, Status};
+use crate::indicator::{classify, IndicatorKind};
+use crate::types::{Graph, Node, Properties};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read};
+
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+#[derive(Serialize, Debug)]
+pub struct IndicatorStatus {
+    pub input: String,
+    pub value: String,
+    pub kind: IndicatorKind,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub nodes: usize,
+    pub edges: usize,
+}
+
+// Reads one indicator per line from a file, or from stdin when `input` is
+// "-". Blank lines and lines starting with '#' are ignored, and only the
+// first comma/whitespace separated field of a line is used.
+pub fn read_indicators(input: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let data = if input == "-" {
+        let mut data = String::new();
+        io::stdin().read_to_string(&mut data)?;
+        data
+    } else {
+        fs::read_to_string(input)?
+    };
+
+    Ok(data
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split([',', ' ', '\t']).next())
+        .map(str::to_string)
+        .collect())
+}
+
+// Looks every indicator up through `enricher` (VirusTotal for vtbulk) and
+// folds the results into one graph, so infrastructure shared between
+// indicators appears once. The VT client's rate limiter keeps the overall
+// request rate in check however many lookups run concurrently.
+pub async fn enrich_bulk(
+    enricher: &dyn Enricher,
+    indicators: Vec<String>,
+    concurrency: usize,
+) -> (Graph, Vec<IndicatorStatus>) {
+    let mut seen = HashSet::new();
+    let mut report = Vec::new();
+    let mut graph = Graph::default();
+
+    for input in indicators {
+        let indicator = classify(&input);
+        let mut properties = Properties::new();
+        properties.insert("indicator_type".to_string(), indicator.kind.as_str().to_string());
+        let seed = Node {
+            id: None,
+            label: indicator.value.clone(),
+            node_type: indicator.kind.node_type().to_string(),
+            properties,
+            ..Default::default()
+        };
+
+        let skip_reason = if !enricher.supports(&seed) {
+            Some(format!("{} indicators are not supported by {}", indicator.kind.as_str(), enricher.name()))
+        } else if !seen.insert(indicator.value.clone()) {
+            Some("duplicate".to_string())
+        } else {
+            None
+        };
+        if skip_reason.is_none() {
+            graph.nodes.push(seed);
+        }
+        report.push(IndicatorStatus {
+            input,
+            value: indicator.value,
+            kind: indicator.kind,
+            status: Status::Skipped,
+            error: skip_reason,
+            nodes: 0,
+            edges: 0,
+        });
+    }
+
+    let mut results: HashMap<String, EnrichmentStatus> = enrich_graph(&mut graph, &[enricher], concurrency)
+        .await
+        .into_iter()
+        .map(|status| (status.label.clone(), status))
+        .collect();
+
+    for entry in report.iter_mut().filter(|r| r.error.is_none()) {
+        if let Some(result) = results.remove(&entry.value) {
+            entry.status = result.status;
+            entry.error = result.error;
+            entry.nodes = result.nodes;
+            entry.edges = result.edges;
+        }
+    }
+
+    (graph, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enrich::VTEnricher;
+    use crate::vt_api::VTClient;
+    use crate::vt_mock::{spawn_mock_vt, MOCK_API_KEY};
+
+    #[test]
+    fn test_read_indicators() {
+        let path = std::env::temp_dir().join(format!("ugh_bulk_{}.txt", std::process::id()));
+        fs::write(&path, "# campaign\nexample.com\n\n192.0.2.10,seen in phish\n  evil[.]example.org  \n").unwrap();
+        let indicators = read_indicators(path.to_str().unwrap()).unwrap();
+        assert_eq!(indicators, vec!["example.com", "192.0.2.10", "evil[.]example.org"]);
+    }
+
+    #[tokio::test]
+    async fn test_enrich_bulk_merges_shared_infrastructure() {
+        let enricher = VTEnricher::new(VTClient::with_config(&spawn_mock_vt(), MOCK_API_KEY));
+        let indicators = vec![
+            "example.com".to_string(),
+            "192.0.2.10".to_string(),
+            "4f2d8e1c7a9b3d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f708192a3b4c5d6".to_string(),
+            "example[.]com".to_string(),
+            "admin@example.com".to_string(),
+            "missing.example.org".to_string(),
+        ];
+        let (graph, report) = enrich_bulk(&enricher, indicators, 3).await;
+
+        let count = |status: Status| report.iter().filter(|r| r.status == status).count();
+        assert_eq!(report.len(), 6);
+        assert_eq!(report[3].error.as_deref(), Some("duplicate"));
+        assert_eq!(count(Status::Ok), 3);
+        assert_eq!(count(Status::Skipped), 2);
+        assert_eq!(count(Status::Failed), 1);
+
+        let labels: Vec<&str> = graph.nodes.iter().map(|n| n.label.as_str()).collect();
+        assert_eq!(labels.iter().filter(|l| **l == "192.0.2.10").count(), 1);
+        assert_eq!(labels.iter().filter(|l| **l == "example.com").count(), 1);
+        assert!(graph
+            .edges
+            .iter()
+            .any(|e| e.source == "example.com" && e.target == "192.0.2.10" && e.relation_type == "resolves_to"));
+    }
+}
@@ -0,0 +1,519 @@
+use crate::types::{Edge, Graph, GraphMetadata, Node, Provenance};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+// What changed between two snapshots of a graph, e.g. the same VT pivots
+// re-run a week apart. Nodes are matched on their label and edges on
+// source, target and relation, as `Graph::absorb` does; when a graph has
+// several nodes (or edges) with the same label, the n-th one of the old
+// graph is matched with the n-th one of the new graph. Applying the patch
+// to the older graph gives the newer one, except for provenance entries of
+// nodes and edges that did not otherwise change (re-running a lookup
+// refreshes every `retrieved_at`).
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct GraphPatch {
+    // The header before and after, when it changed; `new` is None when the
+    // newer graph has no header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<ValueChange<GraphMetadata>>,
+    #[serde(default)]
+    pub added_nodes: Vec<Node>,
+    #[serde(default)]
+    pub removed_nodes: Vec<NodeRef>,
+    #[serde(default)]
+    pub changed_nodes: Vec<NodeChange>,
+    #[serde(default)]
+    pub added_edges: Vec<Edge>,
+    #[serde(default)]
+    pub removed_edges: Vec<EdgeRef>,
+    #[serde(default)]
+    pub changed_edges: Vec<EdgeChange>,
+}
+
+// A node of the graph a patch applies to. `occurrence` counts earlier nodes
+// with the same label and is left out when 0.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NodeRef {
+    pub label: String,
+    #[serde(default, skip_serializing_if = "is_first")]
+    pub occurrence: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EdgeRef {
+    pub source: String,
+    pub target: String,
+    pub relation_type: String,
+    #[serde(default, skip_serializing_if = "is_first")]
+    pub occurrence: usize,
+}
+
+fn is_first(occurrence: &usize) -> bool {
+    *occurrence == 0
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NodeChange {
+    #[serde(flatten)]
+    pub node: NodeRef,
+    #[serde(flatten)]
+    pub changes: Changes,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EdgeChange {
+    #[serde(flatten)]
+    pub edge: EdgeRef,
+    #[serde(flatten)]
+    pub changes: Changes,
+}
+
+// A value before and after; None is "not set".
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ValueChange<T> {
+    pub old: Option<T>,
+    pub new: Option<T>,
+}
+
+// The fields of one node or edge that differ. `provenance` is the new
+// provenance list, carried along so the patch reproduces it.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Changes {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<ValueChange<i32>>,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub node_type: Option<ValueChange<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_seen: Option<ValueChange<DateTime<Utc>>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<ValueChange<DateTime<Utc>>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<ValueChange<u64>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub properties: BTreeMap<String, ValueChange<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Vec<Provenance>>,
+}
+
+impl Changes {
+    fn is_empty(&self) -> bool {
+        self.id.is_none()
+            && self.node_type.is_none()
+            && self.first_seen.is_none()
+            && self.last_seen.is_none()
+            && self.count.is_none()
+            && self.properties.is_empty()
+    }
+}
+
+impl GraphPatch {
+    // No node or edge changed; the header may still differ.
+    pub fn is_empty(&self) -> bool {
+        self.added_nodes.is_empty()
+            && self.removed_nodes.is_empty()
+            && self.changed_nodes.is_empty()
+            && self.added_edges.is_empty()
+            && self.removed_edges.is_empty()
+            && self.changed_edges.is_empty()
+    }
+}
+
+fn change<T: PartialEq + Clone>(old: &Option<T>, new: &Option<T>) -> Option<ValueChange<T>> {
+    (old != new).then(|| ValueChange { old: old.clone(), new: new.clone() })
+}
+
+fn node_refs(nodes: &[Node]) -> Vec<NodeRef> {
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    nodes
+        .iter()
+        .map(|node| {
+            let occurrence = seen.entry(node.label.as_str()).or_default();
+            *occurrence += 1;
+            NodeRef { label: node.label.clone(), occurrence: *occurrence - 1 }
+        })
+        .collect()
+}
+
+fn edge_refs(edges: &[Edge]) -> Vec<EdgeRef> {
+    let mut seen: HashMap<(&str, &str, &str), usize> = HashMap::new();
+    edges
+        .iter()
+        .map(|edge| {
+            let occurrence = seen.entry((&edge.source, &edge.target, &edge.relation_type)).or_default();
+            *occurrence += 1;
+            EdgeRef {
+                source: edge.source.clone(),
+                target: edge.target.clone(),
+                relation_type: edge.relation_type.clone(),
+                occurrence: *occurrence - 1,
+            }
+        })
+        .collect()
+}
+
+fn property_changes(
+    old: &HashMap<String, String>,
+    new: &HashMap<String, String>,
+) -> BTreeMap<String, ValueChange<String>> {
+    old.keys()
+        .chain(new.keys())
+        .filter_map(|key| {
+            let (old, new) = (old.get(key).cloned(), new.get(key).cloned());
+            change(&old, &new).map(|c| (key.clone(), c))
+        })
+        .collect()
+}
+
+fn node_changes(old: &Node, new: &Node) -> Changes {
+    let mut changes = Changes {
+        id: change(&old.id, &new.id),
+        node_type: change(&Some(old.node_type.clone()), &Some(new.node_type.clone())),
+        first_seen: change(&old.first_seen, &new.first_seen),
+        last_seen: change(&old.last_seen, &new.last_seen),
+        count: change(&old.count, &new.count),
+        properties: property_changes(&old.properties.other, &new.properties.other),
+        provenance: None,
+    };
+    if !changes.is_empty() && old.provenance != new.provenance {
+        changes.provenance = Some(new.provenance.clone());
+    }
+    changes
+}
+
+fn edge_changes(old: &Edge, new: &Edge) -> Changes {
+    let mut changes = Changes {
+        first_seen: change(&old.first_seen, &new.first_seen),
+        last_seen: change(&old.last_seen, &new.last_seen),
+        count: change(&old.count, &new.count),
+        properties: property_changes(&old.properties.other, &new.properties.other),
+        ..Default::default()
+    };
+    if !changes.is_empty() && old.provenance != new.provenance {
+        changes.provenance = Some(new.provenance.clone());
+    }
+    changes
+}
+
+// Compares `old` with `new`. Added and changed elements are listed in the
+// order of `new`, removed ones in the order of `old`.
+pub fn diff_graphs(old: &Graph, new: &Graph) -> GraphPatch {
+    let mut patch = GraphPatch {
+        metadata: change(&old.metadata, &new.metadata),
+        ..Default::default()
+    };
+
+    let old_nodes: HashMap<NodeRef, &Node> = node_refs(&old.nodes).into_iter().zip(&old.nodes).collect();
+    let new_refs = node_refs(&new.nodes);
+    for (node_ref, node) in new_refs.iter().zip(&new.nodes) {
+        match old_nodes.get(node_ref) {
+            None => patch.added_nodes.push(node.clone()),
+            Some(old_node) => {
+                let changes = node_changes(old_node, node);
+                if !changes.is_empty() {
+                    patch.changed_nodes.push(NodeChange { node: node_ref.clone(), changes });
+                }
+            }
+        }
+    }
+    let new_refs: HashSet<NodeRef> = new_refs.into_iter().collect();
+    patch.removed_nodes = node_refs(&old.nodes).into_iter().filter(|n| !new_refs.contains(n)).collect();
+
+    let old_edges: HashMap<EdgeRef, &Edge> = edge_refs(&old.edges).into_iter().zip(&old.edges).collect();
+    let new_refs = edge_refs(&new.edges);
+    for (edge_ref, edge) in new_refs.iter().zip(&new.edges) {
+        match old_edges.get(edge_ref) {
+            None => patch.added_edges.push(edge.clone()),
+            Some(old_edge) => {
+                let changes = edge_changes(old_edge, edge);
+                if !changes.is_empty() {
+                    patch.changed_edges.push(EdgeChange { edge: edge_ref.clone(), changes });
+                }
+            }
+        }
+    }
+    let new_refs: HashSet<EdgeRef> = new_refs.into_iter().collect();
+    patch.removed_edges = edge_refs(&old.edges).into_iter().filter(|e| !new_refs.contains(e)).collect();
+    patch
+}
+
+fn set<T: PartialEq + Clone>(
+    name: &str,
+    field: &str,
+    current: &mut Option<T>,
+    change: &ValueChange<T>,
+) -> Result<(), Box<dyn Error>> {
+    if *current != change.old {
+        return Err(format!("patch does not apply: {} {} has changed", name, field).into());
+    }
+    *current = change.new.clone();
+    Ok(())
+}
+
+// Applies `changes` to a node or edge, checking that each old value is what
+// the patch expects.
+macro_rules! apply_changes {
+    ($name:expr, $changes:expr, $element:expr) => {{
+        let (name, changes) = ($name, $changes);
+        if let Some(change) = &changes.first_seen {
+            set(name, "first_seen", &mut $element.first_seen, change)?;
+        }
+        if let Some(change) = &changes.last_seen {
+            set(name, "last_seen", &mut $element.last_seen, change)?;
+        }
+        if let Some(change) = &changes.count {
+            set(name, "count", &mut $element.count, change)?;
+        }
+        for (key, change) in &changes.properties {
+            let mut current = $element.properties.other.get(key).cloned();
+            set(name, key, &mut current, change)?;
+            match current {
+                Some(value) => $element.properties.other.insert(key.clone(), value),
+                None => $element.properties.other.remove(key),
+            };
+        }
+        if let Some(provenance) = &changes.provenance {
+            $element.provenance = provenance.clone();
+        }
+    }};
+}
+
+// Turns the older graph of a diff into the newer one. Fails without
+// touching `graph` when something the patch removes or changes is missing
+// or no longer has the value the patch expects.
+pub fn apply_patch(graph: &mut Graph, patch: &GraphPatch) -> Result<(), Box<dyn Error>> {
+    let mut metadata = graph.metadata.clone();
+    if let Some(change) = &patch.metadata {
+        set("graph", "metadata", &mut metadata, change)?;
+    }
+
+    let mut nodes = graph.nodes.clone();
+    let node_index: HashMap<NodeRef, usize> = node_refs(&nodes).into_iter().zip(0..).collect();
+    let find_node = |node: &NodeRef| {
+        node_index.get(node).copied().ok_or_else(|| format!("patch does not apply: node {} is missing", node))
+    };
+    for change in &patch.changed_nodes {
+        let name = change.node.to_string();
+        let node = &mut nodes[find_node(&change.node)?];
+        if let Some(id_change) = &change.changes.id {
+            set(&name, "id", &mut node.id, id_change)?;
+        }
+        if let Some(type_change) = &change.changes.node_type {
+            let mut node_type = Some(node.node_type.clone());
+            set(&name, "type", &mut node_type, type_change)?;
+            node.node_type = node_type.unwrap_or_default();
+        }
+        apply_changes!(&name, &change.changes, node);
+    }
+    let removed: HashSet<usize> = patch.removed_nodes.iter().map(find_node).collect::<Result<_, _>>()?;
+    let mut nodes: Vec<Node> =
+        nodes.into_iter().enumerate().filter(|(i, _)| !removed.contains(i)).map(|(_, n)| n).collect();
+    nodes.extend(patch.added_nodes.iter().cloned());
+
+    let mut edges = graph.edges.clone();
+    let edge_index: HashMap<EdgeRef, usize> = edge_refs(&edges).into_iter().zip(0..).collect();
+    let find_edge = |edge: &EdgeRef| {
+        edge_index.get(edge).copied().ok_or_else(|| format!("patch does not apply: edge {} is missing", edge))
+    };
+    for change in &patch.changed_edges {
+        let name = change.edge.to_string();
+        let edge = &mut edges[find_edge(&change.edge)?];
+        apply_changes!(&name, &change.changes, edge);
+    }
+    let removed: HashSet<usize> = patch.removed_edges.iter().map(find_edge).collect::<Result<_, _>>()?;
+    let mut edges: Vec<Edge> =
+        edges.into_iter().enumerate().filter(|(i, _)| !removed.contains(i)).map(|(_, e)| e).collect();
+    edges.extend(patch.added_edges.iter().cloned());
+
+    graph.metadata = metadata;
+    graph.nodes = nodes;
+    graph.edges = edges;
+    Ok(())
+}
+
+// Multi-line labels (Maltego CPE blobs) are shown on one line; repeated
+// labels are numbered from the second one on.
+impl fmt::Display for NodeRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.label.split_whitespace().collect::<Vec<_>>().join(" "))?;
+        if self.occurrence > 0 {
+            write!(f, " #{}", self.occurrence + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for EdgeRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} -{}-> {}", self.source, self.relation_type, self.target)?;
+        if self.occurrence > 0 {
+            write!(f, " #{}", self.occurrence + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Changes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn show<T: fmt::Display>(value: &Option<T>) -> String {
+            value.as_ref().map_or("(none)".to_string(), |v| v.to_string())
+        }
+        let mut parts = Vec::new();
+        if let Some(c) = &self.id {
+            parts.push(format!("id {} -> {}", show(&c.old), show(&c.new)));
+        }
+        if let Some(c) = &self.node_type {
+            parts.push(format!("type {} -> {}", show(&c.old), show(&c.new)));
+        }
+        for (field, c) in [("first_seen", &self.first_seen), ("last_seen", &self.last_seen)] {
+            if let Some(c) = c {
+                parts.push(format!("{} {} -> {}", field, show(&c.old), show(&c.new)));
+            }
+        }
+        if let Some(c) = &self.count {
+            parts.push(format!("count {} -> {}", show(&c.old), show(&c.new)));
+        }
+        for (key, c) in &self.properties {
+            parts.push(match (&c.old, &c.new) {
+                (None, Some(new)) => format!("+{}={}", key, new),
+                (Some(old), None) => format!("-{}={}", key, old),
+                (old, new) => format!("{} {} -> {}", key, show(old), show(new)),
+            });
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+// One line per added (+), removed (-) and changed (~) header, node and
+// edge, followed by the totals.
+impl fmt::Display for GraphPatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.metadata.as_ref().map(|c| (&c.old, &c.new)) {
+            Some((None, _)) => writeln!(f, "+ metadata")?,
+            Some((_, None)) => writeln!(f, "- metadata")?,
+            Some(_) => writeln!(f, "~ metadata")?,
+            None => {}
+        }
+        for node in &self.added_nodes {
+            let node_ref = NodeRef { label: node.label.clone(), occurrence: 0 };
+            writeln!(f, "+ node {} ({})", node_ref, node.node_type)?;
+        }
+        for node in &self.removed_nodes {
+            writeln!(f, "- node {}", node)?;
+        }
+        for change in &self.changed_nodes {
+            writeln!(f, "~ node {}: {}", change.node, change.changes)?;
+        }
+        for edge in &self.added_edges {
+            writeln!(f, "+ edge {} -{}-> {}", edge.source, edge.relation_type, edge.target)?;
+        }
+        for edge in &self.removed_edges {
+            writeln!(f, "- edge {}", edge)?;
+        }
+        for change in &self.changed_edges {
+            writeln!(f, "~ edge {}: {}", change.edge, change.changes)?;
+        }
+        write!(
+            f,
+            "nodes: {} added, {} removed, {} changed; edges: {} added, {} removed, {} changed",
+            self.added_nodes.len(),
+            self.removed_nodes.len(),
+            self.changed_nodes.len(),
+            self.added_edges.len(),
+            self.removed_edges.len(),
+            self.changed_edges.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Properties;
+
+    fn node(label: &str, node_type: &str, props: &[(&str, &str)]) -> Node {
+        Node {
+            label: label.to_string(),
+            node_type: node_type.to_string(),
+            properties: Properties::map_values(props.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()),
+            ..Default::default()
+        }
+    }
+
+    fn edge(source: &str, target: &str, relation_type: &str) -> Edge {
+        Edge {
+            source: source.to_string(),
+            target: target.to_string(),
+            relation_type: relation_type.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_diff_and_patch() {
+        let mut maltego_node = node("192.0.2.99", "ip_address", &[]);
+        maltego_node.id = Some(3);
+        let last_week = Graph {
+            metadata: Some(GraphMetadata { name: Some("last week".to_string()), ..Default::default() }),
+            nodes: vec![
+                maltego_node.clone(),
+                node("evil.example", "domain", &[("registrar", "NameCheap"), ("country", "NL")]),
+                node("192.0.2.10", "ip_address", &[]),
+                node("old.example", "domain", &[]),
+                node("Unnamed Node", "unknown", &[("note", "a")]),
+                node("Unnamed Node", "unknown", &[("note", "b")]),
+            ],
+            edges: vec![
+                edge("evil.example", "192.0.2.10", "resolves_to"),
+                edge("old.example", "192.0.2.10", "resolves_to"),
+            ],
+        };
+        let mut resolves = edge("evil.example", "192.0.2.10", "resolves_to");
+        resolves.count = Some(3);
+        maltego_node.id = Some(7);
+        let this_week = Graph {
+            nodes: vec![
+                maltego_node,
+                node("evil.example", "domain", &[("registrar", "NameCheap"), ("country", "DE"), ("asn", "64500")]),
+                node("192.0.2.10", "ip_address", &[]),
+                node("198.51.100.7", "ip_address", &[]),
+                node("Unnamed Node", "unknown", &[("note", "a")]),
+            ],
+            edges: vec![resolves, edge("evil.example", "198.51.100.7", "resolves_to")],
+            ..Default::default()
+        };
+
+        let patch = diff_graphs(&last_week, &this_week);
+        assert_eq!(patch.added_nodes.len(), 1);
+        assert_eq!(patch.removed_nodes[0], NodeRef { label: "old.example".to_string(), occurrence: 0 });
+        assert_eq!(patch.removed_nodes[1].to_string(), "Unnamed Node #2");
+        assert_eq!(patch.changed_nodes.len(), 2);
+        assert_eq!(patch.changed_nodes[0].changes.id, Some(ValueChange { old: Some(3), new: Some(7) }));
+        assert_eq!(patch.changed_nodes[1].changes.properties.len(), 2);
+        assert_eq!(patch.removed_edges[0].source, "old.example");
+        assert_eq!(patch.changed_edges[0].changes.count, Some(ValueChange { old: None, new: Some(3) }));
+        let summary = patch.to_string();
+        assert!(summary.contains("~ node evil.example: +asn=64500, country NL -> DE"));
+        assert!(summary.starts_with("- metadata\n"));
+        assert!(summary.ends_with("nodes: 1 added, 2 removed, 2 changed; edges: 1 added, 1 removed, 1 changed"));
+
+        // The patch survives a round trip through JSON and reproduces the new graph.
+        let patch: GraphPatch = serde_json::from_str(&serde_json::to_string(&patch).unwrap()).unwrap();
+        let mut patched = Graph {
+            metadata: last_week.metadata.clone(),
+            nodes: last_week.nodes.clone(),
+            edges: last_week.edges.clone(),
+        };
+        apply_patch(&mut patched, &patch).unwrap();
+        let again = diff_graphs(&patched, &this_week);
+        assert!(again.is_empty() && again.metadata.is_none());
+        assert!(patched.metadata.is_none());
+
+        // A graph that moved on since the diff is left alone.
+        let mut moved_on = this_week;
+        assert!(apply_patch(&mut moved_on, &patch).is_err());
+        assert_eq!(moved_on.nodes.len(), 5);
+    }
+}
@@ -0,0 +1,373 @@
+use crate::indicator::classify;
+use crate::types::{Edge, Graph, Node, Provenance};
+use crate::vt_api::VTClient;
+use crate::vt_parser::parse_vt_json;
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+// What an enricher found out about one node: properties to set on the node
+// itself plus new neighbours and the edges connecting them. Edges refer to
+// nodes by label, like everywhere else in the graph.
+#[derive(Debug, Default)]
+pub struct Enrichment {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+    pub properties: HashMap<String, String>,
+}
+
+// A source of context for graph nodes (VirusTotal, passive DNS, a sandbox,
+// an in-house lookup, ...). Enrichers only see one node at a time, so they
+// work on any graph, including imported Maltego/GraphML ones.
+#[async_trait]
+pub trait Enricher: Send + Sync {
+    fn name(&self) -> &str;
+
+    // Whether this enricher has anything to say about `node`; unsupported
+    // nodes are skipped without a lookup.
+    fn supports(&self, node: &Node) -> bool;
+
+    async fn enrich(&self, node: &Node) -> Result<Enrichment, Box<dyn Error>>;
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Failed,
+    Skipped,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EnrichmentStatus {
+    pub label: String,
+    pub enricher: String,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub nodes: usize,
+    pub edges: usize,
+}
+
+// Which nodes of a graph to enrich. Empty means every node an enricher
+// supports; otherwise a node is picked when its type or its label is listed.
+#[derive(Debug, Default, Clone)]
+pub struct NodeSelection {
+    pub types: Vec<String>,
+    pub labels: Vec<String>,
+}
+
+impl NodeSelection {
+    // "type:ip,type:domain,evil.example" -> ip and domain nodes plus the node
+    // labelled evil.example.
+    pub fn parse(spec: &str) -> Self {
+        let mut selection = NodeSelection::default();
+        for item in spec.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+            match item.strip_prefix("type:") {
+                Some(node_type) => selection.types.push(node_type.to_lowercase()),
+                None => selection.labels.push(item.to_string()),
+            }
+        }
+        selection
+    }
+
+    pub fn matches(&self, node: &Node) -> bool {
+        (self.types.is_empty() && self.labels.is_empty())
+            || self.types.iter().any(|t| node.node_type.eq_ignore_ascii_case(t))
+            || self.labels.contains(&node.label)
+    }
+}
+
+// Runs every enricher against every node of `graph` it supports, at most
+// `concurrency` lookups at a time, and merges the results back in. Only the
+// nodes present before enrichment are looked up; new neighbours are not
+// followed. Returns one status per attempted (node, enricher) pair.
+pub async fn enrich_graph(
+    graph: &mut Graph,
+    enrichers: &[&dyn Enricher],
+    concurrency: usize,
+) -> Vec<EnrichmentStatus> {
+    enrich_selected(graph, enrichers, &NodeSelection::default(), concurrency).await
+}
+
+// Like `enrich_graph`, restricted to the nodes picked by `selection`.
+pub async fn enrich_selected(
+    graph: &mut Graph,
+    enrichers: &[&dyn Enricher],
+    selection: &NodeSelection,
+    concurrency: usize,
+) -> Vec<EnrichmentStatus> {
+    let jobs: Vec<(usize, &dyn Enricher)> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, node)| selection.matches(node))
+        .flat_map(|(i, node)| {
+            enrichers
+                .iter()
+                .filter(move |enricher| enricher.supports(node))
+                .map(move |enricher| (i, *enricher))
+        })
+        .collect();
+
+    // The lookups are built up front rather than inside a stream closure so
+    // the returned future stays Send (axum handlers need that).
+    let lookups: Vec<_> = jobs
+        .into_iter()
+        .enumerate()
+        .map(|(order, (i, enricher))| run_lookup(order, i, &graph.nodes[i], enricher))
+        .collect();
+    let mut results: Vec<_> = stream::iter(lookups)
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+    results.sort_by_key(|(order, ..)| *order);
+
+    let enriched_at = Utc::now();
+    let mut report = Vec::new();
+    for (_, i, enricher, result) in results {
+        let mut status = EnrichmentStatus {
+            label: graph.nodes[i].label.clone(),
+            enricher: enricher.clone(),
+            status: Status::Ok,
+            error: None,
+            nodes: 0,
+            edges: 0,
+        };
+        match result {
+            Ok(enrichment) => {
+                status.nodes = enrichment.nodes.len();
+                status.edges = enrichment.edges.len();
+                apply_enrichment(graph, i, enrichment, &enricher, enriched_at);
+            }
+            Err(e) => {
+                status.status = Status::Failed;
+                status.error = Some(e);
+            }
+        }
+        report.push(status);
+    }
+    report
+}
+
+async fn run_lookup(
+    order: usize,
+    index: usize,
+    node: &Node,
+    enricher: &dyn Enricher,
+) -> (usize, usize, String, Result<Enrichment, String>) {
+    let result = enricher.enrich(node).await.map_err(|e| e.to_string());
+    (order, index, enricher.name().to_string(), result)
+}
+
+// Merges one enrichment into the graph. The enriched node records which
+// enrichers touched it and when; nodes and edges an enricher introduced carry
+// `added_by` so they can be told apart from the original graph. Everything
+// the enricher supplied is credited to it in the provenance unless it came
+// with its own.
+fn apply_enrichment(graph: &mut Graph, index: usize, enrichment: Enrichment, enricher: &str, enriched_at: DateTime<Utc>) {
+    if enrichment.properties.is_empty() && enrichment.nodes.is_empty() && enrichment.edges.is_empty() {
+        return;
+    }
+
+    let provenance = Provenance::new(enricher, None).at(enriched_at);
+    let node = &mut graph.nodes[index];
+    let mut adopted = Vec::new();
+    for (key, value) in enrichment.properties {
+        if !node.properties.other.contains_key(&key) {
+            node.properties.insert(key.clone(), value);
+            adopted.push(key);
+        }
+    }
+    adopted.sort();
+    node.add_provenance(Provenance { properties: adopted, ..provenance.clone() });
+    let enriched_by = match node.properties.get("enriched_by") {
+        Some(existing) if existing.split(',').any(|e| e == enricher) => existing.clone(),
+        Some(existing) => format!("{},{}", existing, enricher),
+        None => enricher.to_string(),
+    };
+    node.properties.insert("enriched_by".to_string(), enriched_by);
+    node.properties.insert("enriched_at".to_string(), enriched_at.to_rfc3339());
+
+    let known: HashSet<&str> = graph.nodes.iter().map(|n| n.label.as_str()).collect();
+    let mut nodes = enrichment.nodes;
+    for node in nodes.iter_mut().filter(|n| !known.contains(n.label.as_str())) {
+        node.properties.insert("added_by".to_string(), enricher.to_string());
+    }
+    let mut edges = enrichment.edges;
+    for edge in edges.iter_mut() {
+        edge.properties.insert("added_by".to_string(), enricher.to_string());
+    }
+    let mut found = Graph { nodes, edges, ..Default::default() };
+    found.attribute(&provenance);
+    graph.absorb(found);
+}
+
+pub struct VTEnricher {
+    client: VTClient,
+}
+
+impl VTEnricher {
+    pub fn new(client: VTClient) -> Self {
+        VTEnricher { client }
+    }
+}
+
+#[async_trait]
+impl Enricher for VTEnricher {
+    fn name(&self) -> &str {
+        "virustotal"
+    }
+
+    fn supports(&self, node: &Node) -> bool {
+        classify(&node.label).kind.vt_collection().is_some()
+    }
+
+    async fn enrich(&self, node: &Node) -> Result<Enrichment, Box<dyn Error>> {
+        let indicator = classify(&node.label);
+        let vt_data = self
+            .client
+            .call_vt_hal(&indicator.value, indicator.kind.vt_collection())
+            .await?;
+        let (nodes, edges) = parse_vt_json(&vt_data)?;
+        Ok(attach_to(node, &indicator.value, nodes, edges))
+    }
+}
+
+// The VT parser emits the queried indicator as its own node. Fold that node
+// into `node` (which may be labelled with a defanged or differently cased
+// form of the same indicator) and point its edges at `node` instead.
+fn attach_to(node: &Node, query: &str, nodes: Vec<Node>, edges: Vec<Edge>) -> Enrichment {
+    let mut enrichment = Enrichment::default();
+    for found in nodes {
+        if found.label == query {
+            enrichment.properties.extend(found.properties);
+        } else {
+            enrichment.nodes.push(found);
+        }
+    }
+    enrichment.edges = edges
+        .into_iter()
+        .map(|mut edge| {
+            if edge.source == query {
+                edge.source = node.label.clone();
+            }
+            if edge.target == query {
+                edge.target = node.label.clone();
+            }
+            edge
+        })
+        .collect();
+    enrichment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Properties;
+    use crate::vt_mock::{spawn_mock_vt, MOCK_API_KEY};
+
+    fn node(label: &str, node_type: &str) -> Node {
+        Node {
+            id: None,
+            label: label.to_string(),
+            node_type: node_type.to_string(),
+            properties: Properties::new(),
+            ..Default::default()
+        }
+    }
+
+    // Stand-in for an in-house asset inventory lookup.
+    struct Inventory;
+
+    #[async_trait]
+    impl Enricher for Inventory {
+        fn name(&self) -> &str {
+            "inventory"
+        }
+
+        fn supports(&self, node: &Node) -> bool {
+            node.node_type == "host"
+        }
+
+        async fn enrich(&self, host: &Node) -> Result<Enrichment, Box<dyn Error>> {
+            if host.label == "unknown-host" {
+                return Err("not in inventory".into());
+            }
+            let mut enrichment = Enrichment::default();
+            enrichment.properties.insert("owner".to_string(), "it-ops".to_string());
+            enrichment.nodes.push(node("10.0.0.5", "ip"));
+            enrichment.edges.push(Edge {
+                source: host.label.clone(),
+                target: "10.0.0.5".to_string(),
+                relation_type: "has_address".to_string(),
+                properties: Properties::new(),
+                ..Default::default()
+            });
+            Ok(enrichment)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_enrich_graph_with_custom_enricher() {
+        let mut graph = Graph {
+            nodes: vec![node("ws-01", "host"), node("unknown-host", "host"), node("Alice", "person")],
+            edges: Vec::new(),
+            ..Default::default()
+        };
+        let report = enrich_graph(&mut graph, &[&Inventory], 2).await;
+
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[0].status, Status::Ok);
+        assert_eq!(report[1].status, Status::Failed);
+        assert_eq!(graph.nodes.len(), 4);
+        assert_eq!(graph.nodes[0].properties.get("owner").map(String::as_str), Some("it-ops"));
+        assert_eq!(graph.edges.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_enrich_selected_with_provenance() {
+        let mut graph = Graph {
+            nodes: vec![node("ws-01", "host"), node("ws-02", "host"), node("10.0.0.5", "ip")],
+            edges: Vec::new(),
+            ..Default::default()
+        };
+        let selection = NodeSelection::parse("ws-02, type:person");
+        let report = enrich_selected(&mut graph, &[&Inventory], &selection, 2).await;
+
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].label, "ws-02");
+        assert!(graph.nodes[0].properties.get("enriched_by").is_none());
+        assert_eq!(graph.nodes[1].properties.get("enriched_by").map(String::as_str), Some("inventory"));
+        // 10.0.0.5 was already in the graph, so it is not marked as added.
+        assert!(graph.nodes[2].properties.get("added_by").is_none());
+        assert_eq!(graph.edges[0].properties.get("added_by").map(String::as_str), Some("inventory"));
+        assert_eq!(graph.nodes[1].property_sources("owner")[0].source, "inventory");
+        assert_eq!(graph.edges[0].provenance[0].source, "inventory");
+    }
+
+    #[tokio::test]
+    async fn test_vt_enricher_attaches_to_existing_node() {
+        let client = VTClient::with_config(&spawn_mock_vt(), MOCK_API_KEY);
+        let enricher = VTEnricher::new(client);
+        let mut graph = Graph {
+            nodes: vec![node("example[.]com", "domain"), node("Alice", "person")],
+            edges: Vec::new(),
+            ..Default::default()
+        };
+        let report = enrich_graph(&mut graph, &[&enricher], 4).await;
+
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].status, Status::Ok);
+        assert!(graph.nodes.iter().all(|n| n.label != "example.com"));
+        assert!(graph
+            .edges
+            .iter()
+            .any(|e| e.source == "example[.]com" && e.target == "192.0.2.10" && e.relation_type == "resolves_to"));
+        let vt_node = graph.nodes.iter().find(|n| n.label == "192.0.2.10").unwrap();
+        assert!(vt_node.provenance[0].reference.as_deref().unwrap().ends_with("/domains/example.com"));
+    }
+}
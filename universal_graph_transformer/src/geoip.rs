@@ -0,0 +1,236 @@
+use crate::enrich::{Enricher, Enrichment};
+use crate::indicator::{classify, IndicatorKind};
+use crate::types::{Edge, Node, Properties};
+use async_trait::async_trait;
+use maxminddb::{geoip2, Reader};
+use std::env;
+use std::error::Error;
+use std::net::IpAddr;
+
+// Offline geolocation and ASN lookups from local GeoLite2/GeoIP2 City and
+// ASN .mmdb files. Either database may be missing; the enricher then only
+// adds what the other one knows.
+pub struct GeoIpEnricher {
+    city: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+    create_nodes: bool,
+}
+
+impl GeoIpEnricher {
+    pub fn open(city_db: Option<&str>, asn_db: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let open = |path: &str| {
+            Reader::open_readfile(path).map_err(|e| format!("Failed to open GeoIP database {}: {}", path, e))
+        };
+        Ok(GeoIpEnricher {
+            city: city_db.map(open).transpose()?,
+            asn: asn_db.map(open).transpose()?,
+            create_nodes: false,
+        })
+    }
+
+    // GEOIP_CITY_DB / GEOIP_ASN_DB point at the .mmdb files, GEOIP_NODES=1
+    // adds ASN and country nodes. Returns None when neither database is set.
+    pub fn from_env() -> Result<Option<Self>, Box<dyn Error>> {
+        let city_db = env::var("GEOIP_CITY_DB").ok().filter(|v| !v.is_empty());
+        let asn_db = env::var("GEOIP_ASN_DB").ok().filter(|v| !v.is_empty());
+        if city_db.is_none() && asn_db.is_none() {
+            return Ok(None);
+        }
+        let create_nodes = env::var("GEOIP_NODES").map(|v| v == "1" || v == "true").unwrap_or(false);
+        Ok(Some(GeoIpEnricher::open(city_db.as_deref(), asn_db.as_deref())?.with_nodes(create_nodes)))
+    }
+
+    #[cfg(test)]
+    fn from_buffers(city: Option<Vec<u8>>, asn: Option<Vec<u8>>) -> Self {
+        GeoIpEnricher {
+            city: city.map(|buf| Reader::from_source(buf).unwrap()),
+            asn: asn.map(|buf| Reader::from_source(buf).unwrap()),
+            create_nodes: false,
+        }
+    }
+
+    pub fn with_nodes(mut self, create_nodes: bool) -> Self {
+        self.create_nodes = create_nodes;
+        self
+    }
+
+    fn lookup(&self, node: &Node, ip: IpAddr) -> Enrichment {
+        let mut enrichment = Enrichment::default();
+        let mut set = |key: &str, value: String| {
+            enrichment.properties.insert(key.to_string(), value);
+        };
+
+        // Addresses missing from a database are not an error, just unknown.
+        let city = self.city.as_ref().and_then(|r| r.lookup::<geoip2::City>(ip).ok());
+        let mut country_code = None;
+        if let Some(city) = &city {
+            if let Some(country) = &city.country {
+                if let Some(code) = country.iso_code {
+                    set("geo_country", code.to_string());
+                    country_code = Some(code.to_string());
+                }
+                if let Some(name) = english_name(&country.names) {
+                    set("geo_country_name", name);
+                }
+            }
+            if let Some(name) = city.city.as_ref().and_then(|c| english_name(&c.names)) {
+                set("geo_city", name);
+            }
+            if let Some(location) = &city.location {
+                if let (Some(lat), Some(lon)) = (location.latitude, location.longitude) {
+                    set("geo_latitude", lat.to_string());
+                    set("geo_longitude", lon.to_string());
+                }
+            }
+        }
+
+        let asn = self.asn.as_ref().and_then(|r| r.lookup::<geoip2::Asn>(ip).ok());
+        let mut asn_number = None;
+        if let Some(asn) = &asn {
+            if let Some(number) = asn.autonomous_system_number {
+                set("asn", number.to_string());
+                asn_number = Some(number);
+            }
+            if let Some(org) = asn.autonomous_system_organization {
+                set("as_org", org.to_string());
+            }
+        }
+
+        if self.create_nodes {
+            if let Some(number) = asn_number {
+                let mut properties = Properties::new();
+                if let Some(org) = asn.as_ref().and_then(|a| a.autonomous_system_organization) {
+                    properties.insert("as_org".to_string(), org.to_string());
+                }
+                add_neighbour(&mut enrichment, node, format!("AS{}", number), "asn", "announced_by", properties);
+            }
+            if let Some(code) = country_code {
+                let mut properties = Properties::new();
+                if let Some(name) = enrichment.properties.get("geo_country_name") {
+                    properties.insert("name".to_string(), name.clone());
+                }
+                add_neighbour(&mut enrichment, node, code, "country", "located_in", properties);
+            }
+        }
+
+        enrichment
+    }
+}
+
+fn english_name(names: &Option<std::collections::BTreeMap<&str, &str>>) -> Option<String> {
+    names.as_ref().and_then(|n| n.get("en")).map(|n| n.to_string())
+}
+
+fn add_neighbour(
+    enrichment: &mut Enrichment,
+    node: &Node,
+    label: String,
+    node_type: &str,
+    relation_type: &str,
+    properties: Properties,
+) {
+    enrichment.edges.push(Edge {
+        source: node.label.clone(),
+        target: label.clone(),
+        relation_type: relation_type.to_string(),
+        properties: Properties::new(),
+        ..Default::default()
+    });
+    enrichment.nodes.push(Node {
+        id: None,
+        label,
+        node_type: node_type.to_string(),
+        properties,
+        ..Default::default()
+    });
+}
+
+#[async_trait]
+impl Enricher for GeoIpEnricher {
+    fn name(&self) -> &str {
+        "geoip"
+    }
+
+    fn supports(&self, node: &Node) -> bool {
+        matches!(classify(&node.label).kind, IndicatorKind::Ipv4 | IndicatorKind::Ipv6)
+    }
+
+    async fn enrich(&self, node: &Node) -> Result<Enrichment, Box<dyn Error>> {
+        let ip: IpAddr = classify(&node.label).value.parse()?;
+        Ok(self.lookup(node, ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enrich::enrich_graph;
+    use crate::mmdb_fixture::build_mmdb;
+    use crate::types::Graph;
+    use serde_json::json;
+
+    fn test_enricher() -> GeoIpEnricher {
+        let city = build_mmdb(
+            "GeoLite2-City",
+            &[(
+                "192.0.2.0",
+                24,
+                json!({
+                    "city": {"names": {"en": "Amsterdam"}},
+                    "country": {"iso_code": "NL", "names": {"en": "Netherlands"}},
+                    "location": {"latitude": 52.37, "longitude": 4.89}
+                }),
+            )],
+        );
+        let asn = build_mmdb(
+            "GeoLite2-ASN",
+            &[
+                ("192.0.2.0", 24, json!({"autonomous_system_number": 64496, "autonomous_system_organization": "Example Net"})),
+                ("2001:db8::", 32, json!({"autonomous_system_number": 64497, "autonomous_system_organization": "Example v6"})),
+            ],
+        );
+        GeoIpEnricher::from_buffers(Some(city), Some(asn))
+    }
+
+    fn node(label: &str, node_type: &str) -> Node {
+        Node { label: label.to_string(), node_type: node_type.to_string(), ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn test_geoip_properties() {
+        let enricher = test_enricher();
+        let mut graph = Graph {
+            nodes: vec![node("192.0.2.10", "ip"), node("2001:db8::1", "ip"), node("198.51.100.1", "ip"), node("example.com", "domain")],
+            edges: Vec::new(),
+            ..Default::default()
+        };
+        let report = enrich_graph(&mut graph, &[&enricher], 4).await;
+        assert_eq!(report.len(), 3);
+
+        let props = |i: usize, key: &str| graph.nodes[i].properties.get(key).cloned();
+        assert_eq!(props(0, "geo_country").as_deref(), Some("NL"));
+        assert_eq!(props(0, "geo_city").as_deref(), Some("Amsterdam"));
+        assert_eq!(props(0, "geo_latitude").as_deref(), Some("52.37"));
+        assert_eq!(props(0, "asn").as_deref(), Some("64496"));
+        assert_eq!(props(0, "as_org").as_deref(), Some("Example Net"));
+        assert_eq!(props(1, "asn").as_deref(), Some("64497"));
+        assert_eq!(props(1, "geo_country"), None);
+        assert!(graph.nodes[2].properties.other.is_empty());
+        assert_eq!(graph.nodes.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_geoip_nodes() {
+        let enricher = test_enricher().with_nodes(true);
+        let mut graph = Graph {
+            nodes: vec![node("192.0.2.10", "ip"), node("192.0.2.11", "ip")],
+            ..Default::default()
+        };
+        enrich_graph(&mut graph, &[&enricher], 4).await;
+
+        let labels: Vec<&str> = graph.nodes.iter().map(|n| n.label.as_str()).collect();
+        assert_eq!(labels, vec!["192.0.2.10", "192.0.2.11", "AS64496", "NL"]);
+        assert_eq!(graph.edges.len(), 4);
+        assert!(graph.edges.iter().any(|e| e.source == "192.0.2.11" && e.target == "AS64496" && e.relation_type == "announced_by"));
+    }
+}
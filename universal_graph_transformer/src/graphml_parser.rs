@@ -1,17 +1,11 @@
 use quick_xml::events::Event;
 use quick_xml::Reader;
-use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufReader};
 use std::error::Error;
-use crate::types::{Node, Edge, Properties};
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct GraphML {
-    pub nodes: Vec<Node>,
-    pub edges: Vec<Edge>,
-}
+use crate::indicator::{classify, IndicatorKind};
+use crate::types::{Graph, Node, Edge, Properties, Provenance};
 
 pub fn parse_graphml(filename: &str) -> Result<(Vec<Node>, Vec<Edge>), Box<dyn Error>> {
     let file = File::open(filename)?;
@@ -48,6 +42,7 @@ pub fn parse_graphml(filename: &str) -> Result<(Vec<Node>, Vec<Edge>), Box<dyn E
                                     label: "Unnamed Node".to_string(),
                                     node_type: "unknown".to_string(),
                                     properties: Properties::map_values(properties.clone()),
+                                    ..Default::default()
                                 });
                             }
                         }
@@ -80,6 +75,7 @@ pub fn parse_graphml(filename: &str) -> Result<(Vec<Node>, Vec<Edge>), Box<dyn E
                             target,
                             relation_type: if relation_type.is_empty() { "linked_to".to_string() } else { relation_type },
                             properties: Properties::map_values(properties.clone()),
+                            ..Default::default()
                         });
                     }
                     b"mtg:Property" => {
@@ -124,6 +120,10 @@ pub fn parse_graphml(filename: &str) -> Result<(Vec<Node>, Vec<Edge>), Box<dyn E
                                     }
                                 }
                             }
+                            let indicator = classify(&node.label);
+                            if indicator.kind != IndicatorKind::Unknown {
+                                node.properties.insert("indicator_type".to_string(), indicator.kind.as_str().to_string());
+                            }
                             nodes.push(node);
                         }
                     }
@@ -147,7 +147,10 @@ pub fn parse_graphml(filename: &str) -> Result<(Vec<Node>, Vec<Edge>), Box<dyn E
         buf.clear();
     }
 
-    Ok((nodes, edges))
+    let source = if filename.ends_with(".maltego") { "maltego" } else { "graphml" };
+    let mut graph = Graph { nodes, edges, ..Default::default() };
+    graph.attribute(&Provenance::new(source, Some(filename)));
+    Ok((graph.nodes, graph.edges))
 }
 
 #[cfg(test)]
@@ -156,11 +159,18 @@ mod graphml_parser_tests {
 
     #[test]
     fn test_parse_graphml() {
-        let (nodes, edges) = parse_graphml("../example_data/netsupp_2806.graphml").unwrap();
+        let (nodes, edges) = parse_graphml("../web_server/uploads/1caf50f1-286a-4d7f-9ed6-2a02c74a9ea9-netsupp_2806.graphml").unwrap();
         assert_eq!(nodes.len(), 78);
         assert_eq!(edges.len(), 71);
     }
 
+    #[test]
+    fn test_parse_graphml_indicator_types() {
+        let (nodes, _) = parse_graphml("../web_server/uploads/1caf50f1-286a-4d7f-9ed6-2a02c74a9ea9-netsupp_2806.graphml").unwrap();
+        let ip = nodes.iter().find(|n| n.label == "81.19.138.55").unwrap();
+        assert_eq!(ip.properties.get("indicator_type").map(String::as_str), Some("ipv4"));
+    }
+
     #[test]
     fn test_parse_graphml_with_invalid_file() {
         let result = parse_graphml("invalid_file.graphml");
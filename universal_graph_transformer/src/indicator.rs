@@ -0,0 +1,303 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+// Indicator classification shared by the VT client, the parsers and the web
+// server. Input is refanged first, so "hxxp://evil[.]com" is a URL.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndicatorKind {
+    Md5,
+    Sha1,
+    Sha256,
+    Ipv4,
+    Ipv6,
+    Cidr,
+    Email,
+    Domain,
+    Url,
+    Unknown,
+}
+
+impl IndicatorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IndicatorKind::Md5 => "md5",
+            IndicatorKind::Sha1 => "sha1",
+            IndicatorKind::Sha256 => "sha256",
+            IndicatorKind::Ipv4 => "ipv4",
+            IndicatorKind::Ipv6 => "ipv6",
+            IndicatorKind::Cidr => "cidr",
+            IndicatorKind::Email => "email",
+            IndicatorKind::Domain => "domain",
+            IndicatorKind::Url => "url",
+            IndicatorKind::Unknown => "unknown",
+        }
+    }
+
+    // VirusTotal v3 collection that can look this kind up, if any.
+    pub fn vt_collection(&self) -> Option<&'static str> {
+        match self {
+            IndicatorKind::Md5 | IndicatorKind::Sha1 | IndicatorKind::Sha256 => Some("files"),
+            IndicatorKind::Ipv4 | IndicatorKind::Ipv6 => Some("ip_addresses"),
+            IndicatorKind::Domain => Some("domains"),
+            IndicatorKind::Url => Some("urls"),
+            _ => None,
+        }
+    }
+
+    // Node type used for this kind in the universal graph.
+    pub fn node_type(&self) -> &'static str {
+        match self {
+            IndicatorKind::Md5 | IndicatorKind::Sha1 | IndicatorKind::Sha256 => "file",
+            IndicatorKind::Ipv4 | IndicatorKind::Ipv6 => "ip",
+            IndicatorKind::Cidr => "cidr",
+            IndicatorKind::Email => "email",
+            IndicatorKind::Domain => "domain",
+            IndicatorKind::Url => "url",
+            IndicatorKind::Unknown => "unknown",
+        }
+    }
+
+    pub fn is_hash(&self) -> bool {
+        matches!(self, IndicatorKind::Md5 | IndicatorKind::Sha1 | IndicatorKind::Sha256)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Indicator {
+    // Refanged and normalized form, suitable for lookups and as a node label.
+    pub value: String,
+    pub kind: IndicatorKind,
+}
+
+lazy_static! {
+    static ref HXXP: Regex = Regex::new(r"(?i)\bh[x*]{2}p(s?)(\[://\]|\[?:\]?//)").unwrap();
+    static ref DEFANGED_SCHEME: Regex = Regex::new(r"(?i)^(https?|ftp)\[:\]//").unwrap();
+    static ref DEFANGED_DOT: Regex =
+        Regex::new(r"(?i)\[\.\]|\(\.\)|\{\.\}|\[dot\]|\(dot\)|\{dot\}|\s\[?dot\]?\s|\\\.").unwrap();
+    static ref DEFANGED_AT: Regex =
+        Regex::new(r"(?i)\[@\]|\(@\)|\[at\]|\(at\)|\{at\}|\s\[at\]\s").unwrap();
+    static ref DEFANGED_COLON: Regex = Regex::new(r"\[:\]").unwrap();
+    static ref HEX: Regex = Regex::new(r"^[a-fA-F0-9]+$").unwrap();
+    static ref DOMAIN_LABEL: Regex = Regex::new(r"^[a-z0-9](?:[a-z0-9-]{0,61}[a-z0-9])?$").unwrap();
+    static ref TLD: Regex = Regex::new(r"^(?:[a-z]{2,63}|xn--[a-z0-9-]{1,59})$").unwrap();
+    static ref EMAIL_LOCAL: Regex = Regex::new(r"^[A-Za-z0-9.!#$%&'*+/=?^_`{|}~-]+$").unwrap();
+    static ref URL_SCHEME: Regex = Regex::new(r"(?i)^(https?|ftp)://").unwrap();
+}
+
+// File extensions that are not top-level domains, so "payload.exe" or
+// "invoice.js" on its own is a file name rather than a domain. Extensions
+// that are also delegated TLDs (.zip, .mov, .sh, .py, .one, ...) still
+// classify as domains. Host names of URLs and emails are not checked.
+const FILE_EXTENSIONS: &[&str] = &[
+    "exe", "dll", "sys", "drv", "ocx", "cpl", "scr", "pif", "msi", "bat", "cmd", "vbs", "vbe", "js", "jse",
+    "wsf", "hta", "lnk", "jar", "apk", "elf", "dmg", "iso", "img", "bin", "dat", "tmp", "log", "doc", "docx",
+    "docm", "dotm", "xls", "xlsx", "xlsm", "ppt", "pptx", "pdf", "rtf", "txt", "csv", "json", "xml",
+    "htm", "html", "php", "asp", "aspx", "jsp", "cfg", "ini", "eml", "chm", "rar", "tar", "gz", "tgz", "jpg",
+    "jpeg", "png", "gif", "bmp", "svg",
+];
+
+// Undo common defanging: hxxp://, [.], (dot), [@], [:] and friends.
+pub fn refang(input: &str) -> String {
+    let trimmed = input
+        .trim()
+        .trim_matches(|c| c == '"' || c == '\'' || c == '<' || c == '>' || c == '`');
+    let value = HXXP.replace_all(trimmed, "http$1://");
+    let value = DEFANGED_SCHEME.replace_all(&value, "$1://");
+    let value = DEFANGED_DOT.replace_all(&value, ".");
+    let value = DEFANGED_AT.replace_all(&value, "@");
+    DEFANGED_COLON.replace_all(&value, ":").to_string()
+}
+
+pub fn classify(input: &str) -> Indicator {
+    let value = refang(input);
+
+    if HEX.is_match(&value) {
+        let kind = match value.len() {
+            32 => IndicatorKind::Md5,
+            40 => IndicatorKind::Sha1,
+            64 => IndicatorKind::Sha256,
+            _ => IndicatorKind::Unknown,
+        };
+        if kind != IndicatorKind::Unknown {
+            return Indicator { value: value.to_lowercase(), kind };
+        }
+    }
+
+    if let Ok(ip) = value.parse::<Ipv4Addr>() {
+        return Indicator { value: ip.to_string(), kind: IndicatorKind::Ipv4 };
+    }
+    let bare = value.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = bare.parse::<Ipv6Addr>() {
+        return Indicator { value: ip.to_string(), kind: IndicatorKind::Ipv6 };
+    }
+    if let Some(cidr) = normalize_cidr(&value) {
+        return Indicator { value: cidr, kind: IndicatorKind::Cidr };
+    }
+    if URL_SCHEME.is_match(&value) {
+        if let Some(url) = normalize_url(&value) {
+            return Indicator { value: url, kind: IndicatorKind::Url };
+        }
+    }
+    if let Some(email) = normalize_email(&value) {
+        return Indicator { value: email, kind: IndicatorKind::Email };
+    }
+    if let Some(domain) = normalize_domain(&value).filter(|d| !is_file_name(d)) {
+        return Indicator { value: domain, kind: IndicatorKind::Domain };
+    }
+
+    Indicator { value, kind: IndicatorKind::Unknown }
+}
+
+// Lowercases, strips a trailing dot and converts internationalized names to
+// their punycode (xn--) form.
+pub fn normalize_domain(input: &str) -> Option<String> {
+    let input = input.trim().trim_end_matches('.');
+    if input.is_empty() || input.contains(|c: char| c.is_whitespace() || c == '/' || c == '@') {
+        return None;
+    }
+    let ascii = idna::domain_to_ascii(input).ok()?;
+    let labels: Vec<&str> = ascii.split('.').collect();
+    if labels.len() < 2 || ascii.len() > 253 {
+        return None;
+    }
+    let tld = labels[labels.len() - 1];
+    if !TLD.is_match(tld) || !labels.iter().all(|label| DOMAIN_LABEL.is_match(label)) {
+        return None;
+    }
+    Some(ascii)
+}
+
+fn is_file_name(domain: &str) -> bool {
+    domain.rsplit('.').next().is_some_and(|tld| FILE_EXTENSIONS.contains(&tld))
+}
+
+pub fn normalize_ip(input: &str) -> Option<String> {
+    let bare = input.trim().trim_start_matches('[').trim_end_matches(']');
+    bare.parse::<IpAddr>().ok().map(|ip| ip.to_string())
+}
+
+fn normalize_cidr(input: &str) -> Option<String> {
+    let (addr, prefix) = input.split_once('/')?;
+    let prefix: u8 = prefix.parse().ok()?;
+    match addr.parse::<IpAddr>().ok()? {
+        IpAddr::V4(ip) if prefix <= 32 => Some(format!("{}/{}", ip, prefix)),
+        IpAddr::V6(ip) if prefix <= 128 => Some(format!("{}/{}", ip, prefix)),
+        _ => None,
+    }
+}
+
+fn normalize_email(input: &str) -> Option<String> {
+    let (local, domain) = input.rsplit_once('@')?;
+    if !EMAIL_LOCAL.is_match(local) {
+        return None;
+    }
+    let domain = normalize_domain(domain)?;
+    Some(format!("{}@{}", local, domain))
+}
+
+// Keeps path and query as given but normalizes scheme and host so the same
+// URL written two ways classifies to one value.
+fn normalize_url(input: &str) -> Option<String> {
+    let (scheme, rest) = input.split_once("://")?;
+    let host_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (authority, tail) = rest.split_at(host_end);
+    let host_port = authority.rsplit_once('@').map(|(_, h)| h).unwrap_or(authority);
+
+    let (host, port) = if host_port.starts_with('[') {
+        let end = host_port.find(']')?;
+        (&host_port[..=end], &host_port[end + 1..])
+    } else {
+        match host_port.rsplit_once(':') {
+            Some((h, p)) if p.chars().all(|c| c.is_ascii_digit()) => (h, &host_port[h.len()..]),
+            _ => (host_port, ""),
+        }
+    };
+
+    let host = if let Some(ip) = normalize_ip(host) {
+        if host.starts_with('[') {
+            format!("[{}]", ip)
+        } else {
+            ip
+        }
+    } else {
+        normalize_domain(host)?
+    };
+
+    let userinfo = &authority[..authority.len() - host_port.len()];
+    Some(format!("{}://{}{}{}{}", scheme.to_lowercase(), userinfo, host, port, tail))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(input: &str) -> IndicatorKind {
+        classify(input).kind
+    }
+
+    #[test]
+    fn test_hash_lengths() {
+        assert_eq!(kind("44d88612fea8a8f36de82e1278abb02f"), IndicatorKind::Md5);
+        assert_eq!(kind("3395856CE81F2B7382DEE72602F798B642F14140"), IndicatorKind::Sha1);
+        assert_eq!(
+            kind("275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f"),
+            IndicatorKind::Sha256
+        );
+        assert_eq!(kind("275a021bbfb6489e54d471899f7db9d1663fc695ec2f"), IndicatorKind::Unknown);
+        assert_eq!(classify("3395856CE81F2B7382DEE72602F798B642F14140").value, "3395856ce81f2b7382dee72602f798b642f14140");
+    }
+
+    #[test]
+    fn test_ip_and_cidr() {
+        assert_eq!(kind("192.0.2.1"), IndicatorKind::Ipv4);
+        assert_eq!(kind("192[.]0[.]2[.]1"), IndicatorKind::Ipv4);
+        assert_eq!(kind("999.1.1.1"), IndicatorKind::Unknown);
+        assert_eq!(classify("2001:DB8:0:0::1").value, "2001:db8::1");
+        assert_eq!(kind("[2001:db8::1]"), IndicatorKind::Ipv6);
+        assert_eq!(kind("10.0.0.0/8"), IndicatorKind::Cidr);
+        assert_eq!(kind("2001:db8::/32"), IndicatorKind::Cidr);
+        assert_eq!(kind("10.0.0.0/33"), IndicatorKind::Unknown);
+    }
+
+    #[test]
+    fn test_domains_and_emails() {
+        assert_eq!(classify("Evil-Example.COM.").value, "evil-example.com");
+        assert_eq!(classify("evil(dot)example[.]com").value, "evil.example.com");
+        assert_eq!(classify("bücher.example").value, "xn--bcher-kva.example");
+        assert_eq!(kind("xn--bcher-kva.example"), IndicatorKind::Domain);
+        assert_eq!(kind("-bad-.com"), IndicatorKind::Unknown);
+        assert_eq!(kind("localhost"), IndicatorKind::Unknown);
+        assert_eq!(classify("admin[@]Evil[.]com").value, "admin@evil.com");
+        assert_eq!(kind("admin[at]evil.com"), IndicatorKind::Email);
+    }
+
+    #[test]
+    fn test_file_names() {
+        assert_eq!(kind("payload.exe"), IndicatorKind::Unknown);
+        assert_eq!(kind("invoice.js"), IndicatorKind::Unknown);
+        assert_eq!(kind("Report.PDF"), IndicatorKind::Unknown);
+        assert_eq!(kind("update.docm"), IndicatorKind::Unknown);
+        // Extensions that are also TLDs stay domains, as do hosts of URLs.
+        assert_eq!(kind("evil.zip"), IndicatorKind::Domain);
+        assert_eq!(kind("example.dot"), IndicatorKind::Domain);
+        assert_eq!(kind("hxxp://cdn.example.com/payload.exe"), IndicatorKind::Url);
+        assert_eq!(kind("ops@evil.example"), IndicatorKind::Email);
+    }
+
+    #[test]
+    fn test_urls() {
+        let url = classify("hxxps[://]evil[.]example.com/path?a=1");
+        assert_eq!(url.kind, IndicatorKind::Url);
+        assert_eq!(url.value, "https://evil.example.com/path?a=1");
+        assert_eq!(classify("hXXp://192.0.2.1:8080/x").value, "http://192.0.2.1:8080/x");
+        assert_eq!(classify("HTTP://Example.com").value, "http://example.com");
+        assert_eq!(kind("http://[2001:db8::1]/a"), IndicatorKind::Url);
+        assert_eq!(kind("http://not a url"), IndicatorKind::Unknown);
+        assert_eq!(IndicatorKind::Url.vt_collection(), Some("urls"));
+        assert_eq!(IndicatorKind::Email.vt_collection(), None);
+    }
+}
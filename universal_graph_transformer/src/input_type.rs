@@ -10,7 +10,7 @@ mod tests {
 
     #[test]
     fn test_is_file() {
-        assert_eq!(is_file("Cargo.toml"), true);
-        assert_eq!(is_file("Cargo.toml2"), false);
+        assert!(is_file("Cargo.toml"));
+        assert!(!is_file("Cargo.toml2"));
     }
 }
\ No newline at end of file
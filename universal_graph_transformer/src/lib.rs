@@ -1,17 +1,36 @@
 extern crate dotenv;
 
-use crate::transform::transform_graph;
+use crate::enrich::NodeSelection;
+use crate::transform::{
+    describe, diff_graph_files, enrich_existing_graph, patch_graph_file, patch_path, transform_graph,
+    transform_graph_with, window_graph,
+};
 use dotenv::dotenv;
 use std::env;
 use std::process;
 
+pub mod bulk;
+pub mod diff;
+pub mod enrich;
+pub mod geoip;
 mod graphml_parser;
+pub mod indicator;
 mod input_type;
+#[cfg(test)]
+mod mmdb_fixture;
 mod mysecret;
+mod pdns_parser;
+mod rate_limit;
 pub mod transform;
 pub mod types;
-mod vt_api;
+pub mod vt_api;
+pub mod vt_cache;
+pub mod vt_models;
+pub mod vt_relationships;
+#[cfg(test)]
+mod vt_mock;
 mod vt_parser;
+pub mod whois;
 
 #[tokio::main]
 pub async fn main() {
@@ -19,15 +38,29 @@ pub async fn main() {
     let args: Vec<String> = env::args().collect();
 
     if args.len() < 2 {
-        eprintln!("Usage: {} <filename|json_string> [mode]", args[0]);
+        eprintln!("Usage: {} <filename|json_string|indicator|indicator_file|-> [mode] [selection|window|graph|patch]", args[0]);
         process::exit(1);
     }
 
     let input = &args[1];
-    let mode = if args.len() == 3 { &args[2] } else { "auto" };
+    let mode = if args.len() >= 3 { &args[2] } else { "auto" };
     let output_path = "rich.json";
 
-    match transform_graph(input, mode, output_path).await {
+    let result = if mode == "vtenrich" {
+        let selection = NodeSelection::parse(args.get(3).map(String::as_str).unwrap_or(""));
+        enrich_existing_graph(input, &selection, output_path).await.map(|_| ())
+    } else if mode == "window" {
+        window_graph(input, args.get(3).map(String::as_str).unwrap_or(".."), output_path).map(|_| ())
+    } else if mode == "diff" || mode == "patch" {
+        match args.get(3) {
+            Some(other) if mode == "diff" => diff_graph_files(input, other, &patch_path(output_path)).map(|_| ()),
+            Some(patch) => patch_graph_file(input, patch, output_path).map(|_| ()),
+            None => Err(format!("{} needs a second file", mode).into()),
+        }
+    } else {
+        transform_graph(input, mode, output_path).await
+    };
+    match result {
         Ok(_) => println!("Transformation successful!"),
         Err(e) => eprintln!("An error occurred: {}", e),
     }
@@ -40,14 +73,33 @@ pub async fn graph_transformer(input: &str, mode: &str, output_path: &str) -> Re
     }
 }
 
+// Like `graph_transformer`, naming the graph after what the user submitted
+// (an uploaded file's name, an indicator) rather than the stored input.
+pub async fn graph_transformer_named(input: &str, mode: &str, output_path: &str, name: &str) -> Result<(), String> {
+    let mut metadata = describe(input, mode);
+    metadata.name = Some(name.to_string());
+    metadata.description = Some(format!("{} transform of {}", mode, name));
+    match transform_graph_with(input, mode, output_path, metadata).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub async fn graph_enricher(input: &str, selection: &str, output_path: &str) -> Result<(), String> {
+    match enrich_existing_graph(input, &NodeSelection::parse(selection), output_path).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::process::Command;
     #[test]
     fn test_graph_transformer() {
-        let output = Command::new("cargo")
+        let _output = Command::new("cargo")
             .arg("run")
-            .arg("../example_data/netsupp_2806.graphml")
+            .arg("../web_server/uploads/1caf50f1-286a-4d7f-9ed6-2a02c74a9ea9-netsupp_2806.graphml")
             .arg("auto")
             .output()
             .expect("Failed to run cargo run");
@@ -55,7 +107,7 @@ mod tests {
 
     #[test]
     fn test_graph_transformer_with_json() {
-        let output = Command::new("cargo")
+        let _output = Command::new("cargo")
             .arg("run")
             .arg("{\"nodes\": [{\"id\": \"1\", \"label\": \"Node 1\"}, {\"id\": \"2\", \"label\": \"Node 2\"}], \"edges\": [{\"source\": \"1\", \"target\": \"2\", \"label\": \"Edge 1\"}]}")
             .arg("auto")
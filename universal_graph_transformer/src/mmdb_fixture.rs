@@ -0,0 +1,125 @@
+use serde_json::{json, Value};
+use std::net::IpAddr;
+
+// Minimal MaxMind DB writer so the GeoIP tests can build small City/ASN
+// databases in memory instead of shipping licensed GeoLite2 files. Writes an
+// IPv6 tree (IPv4 lives under ::/96, like the real databases) with 24-bit
+// records.
+pub fn build_mmdb(database_type: &str, networks: &[(&str, u8, Value)]) -> Vec<u8> {
+    let mut tree: Vec<[Record; 2]> = vec![[Record::Empty, Record::Empty]];
+    let mut data = Vec::new();
+
+    for (address, prefix, value) in networks {
+        let (bits, prefix) = match address.parse::<IpAddr>().expect("fixture network") {
+            IpAddr::V4(ip) => (u32::from(ip) as u128, *prefix as usize + 96),
+            IpAddr::V6(ip) => (u128::from(ip), *prefix as usize),
+        };
+        let offset = data.len();
+        encode(value, &mut data);
+
+        let mut node = 0;
+        for depth in 0..prefix {
+            let bit = ((bits >> (127 - depth)) & 1) as usize;
+            if depth == prefix - 1 {
+                tree[node][bit] = Record::Data(offset);
+            } else {
+                node = match tree[node][bit] {
+                    Record::Node(next) => next,
+                    _ => {
+                        tree.push([Record::Empty, Record::Empty]);
+                        tree[node][bit] = Record::Node(tree.len() - 1);
+                        tree.len() - 1
+                    }
+                };
+            }
+        }
+    }
+
+    let node_count = tree.len();
+    let mut out = Vec::new();
+    for records in &tree {
+        for record in records {
+            let value = match record {
+                Record::Empty => node_count,
+                Record::Node(next) => *next,
+                Record::Data(offset) => node_count + 16 + offset,
+            };
+            out.extend_from_slice(&(value as u32).to_be_bytes()[1..]);
+        }
+    }
+    out.extend_from_slice(&[0; 16]);
+    out.extend_from_slice(&data);
+    out.extend_from_slice(b"\xAB\xCD\xEFMaxMind.com");
+    encode(
+        &json!({
+            "binary_format_major_version": 2,
+            "binary_format_minor_version": 0,
+            "build_epoch": 1700000000,
+            "database_type": database_type,
+            "description": {"en": "ugh test database"},
+            "ip_version": 6,
+            "languages": ["en"],
+            "node_count": node_count,
+            "record_size": 24
+        }),
+        &mut out,
+    );
+    out
+}
+
+#[derive(Clone, Copy)]
+enum Record {
+    Empty,
+    Node(usize),
+    Data(usize),
+}
+
+fn encode(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::String(s) => {
+            control(out, 2, s.len());
+            out.extend_from_slice(s.as_bytes());
+        }
+        Value::Number(n) if n.is_u64() => {
+            let n = n.as_u64().unwrap();
+            let bytes = n.to_be_bytes();
+            let skip = bytes.iter().take_while(|b| **b == 0).count();
+            control(out, if n <= u32::MAX as u64 { 6 } else { 9 }, 8 - skip);
+            out.extend_from_slice(&bytes[skip..]);
+        }
+        Value::Number(n) => {
+            control(out, 3, 8);
+            out.extend_from_slice(&n.as_f64().unwrap().to_be_bytes());
+        }
+        Value::Bool(b) => control(out, 14, *b as usize),
+        Value::Object(map) => {
+            control(out, 7, map.len());
+            for (key, value) in map {
+                encode(&Value::String(key.clone()), out);
+                encode(value, out);
+            }
+        }
+        Value::Array(items) => {
+            control(out, 11, items.len());
+            for item in items {
+                encode(item, out);
+            }
+        }
+        Value::Null => panic!("MaxMind DB has no null type"),
+    }
+}
+
+fn control(out: &mut Vec<u8>, type_num: u8, size: usize) {
+    let (size_bits, extra) = match size {
+        0..=28 => (size as u8, Vec::new()),
+        29..=284 => (29, vec![(size - 29) as u8]),
+        _ => (30, ((size - 285) as u16).to_be_bytes().to_vec()),
+    };
+    if type_num <= 7 {
+        out.push(type_num << 5 | size_bits);
+    } else {
+        out.push(size_bits);
+        out.push(type_num - 7);
+    }
+    out.extend(extra);
+}
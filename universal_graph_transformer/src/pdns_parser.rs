@@ -0,0 +1,243 @@
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+use crate::indicator::{normalize_domain, normalize_ip};
+use crate::types::{Edge, Graph, Node, Observation, Properties, Provenance};
+use crate::vt_parser::dns_record_edge;
+
+// Passive DNS exports from different providers. JSONL follows the Passive DNS
+// Common Output Format (rrname, rrtype, rdata, time_first, time_last, count)
+// as used by Farsight/CIRCL; CSV exports need a header row and may use the
+// more common first_seen/last_seen names instead.
+const RRNAME: &[&str] = &["rrname", "query", "qname", "domain", "name"];
+const RRTYPE: &[&str] = &["rrtype", "qtype", "type", "record_type"];
+const RDATA: &[&str] = &["rdata", "answer", "value", "ip", "resolve"];
+const FIRST_SEEN: &[&str] = &["time_first", "first_seen", "zone_time_first", "firstseen", "first"];
+const LAST_SEEN: &[&str] = &["time_last", "last_seen", "zone_time_last", "lastseen", "last"];
+const COUNT: &[&str] = &["count", "hits"];
+
+pub fn parse_pdns(filename: &str) -> Result<(Vec<Node>, Vec<Edge>), Box<dyn Error>> {
+    let data = fs::read_to_string(filename)?;
+    let rows = if filename.ends_with(".csv") {
+        read_csv(&data)?
+    } else {
+        read_jsonl(&data)?
+    };
+
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut node_index: HashMap<String, usize> = HashMap::new();
+    let mut edges: Vec<(String, String, &'static str, Observation)> = Vec::new();
+    let mut edge_index: HashMap<(String, String, &'static str), usize> = HashMap::new();
+    let mut skipped = 0;
+
+    for (line, row) in rows.iter().enumerate() {
+        let rrname = lookup(row, RRNAME).and_then(|v| normalize_domain(&v));
+        let rdata = lookup_all(row, RDATA);
+        let Some(rrname) = rrname.filter(|_| !rdata.is_empty()) else {
+            eprintln!("Warning: pDNS record {} has no rrname or rdata", line + 1);
+            skipped += 1;
+            continue;
+        };
+        let observation = Observation {
+            first_seen: lookup(row, FIRST_SEEN).and_then(|v| parse_time(&v)),
+            last_seen: lookup(row, LAST_SEEN).and_then(|v| parse_time(&v)),
+            count: lookup(row, COUNT).and_then(|v| v.parse().ok()),
+        };
+
+        for value in rdata {
+            // Exports without a type column are assumed to be address records.
+            let rrtype = lookup(row, RRTYPE)
+                .map(|t| t.to_uppercase())
+                .unwrap_or_else(|| if value.contains(':') { "AAAA" } else { "A" }.to_string());
+            let Some((node_type, relation_type)) = dns_record_edge(&rrtype) else {
+                skipped += 1;
+                continue;
+            };
+            let target = if node_type == "ip" { normalize_ip(&value) } else { normalize_domain(&value) };
+            let Some(target) = target else {
+                eprintln!("Warning: pDNS record {} has invalid {} rdata {}", line + 1, rrtype, value);
+                skipped += 1;
+                continue;
+            };
+
+            // Both names were live for as long as the resolution was seen;
+            // counts belong to the resolution, not the names.
+            let seen = Observation { count: None, ..observation };
+            for (label, node_type) in [(&rrname, "domain"), (&target, node_type)] {
+                let i = *node_index.entry(label.clone()).or_insert_with(|| {
+                    nodes.push(Node {
+                        id: None,
+                        label: label.clone(),
+                        node_type: node_type.to_string(),
+                        properties: Properties::new(),
+                        ..Default::default()
+                    });
+                    nodes.len() - 1
+                });
+                nodes[i].observe(&seen);
+            }
+
+            // The same resolution reported by several providers or dumps is
+            // one edge whose window covers all sightings.
+            let key = (rrname.clone(), target.clone(), relation_type);
+            match edge_index.get(&key) {
+                Some(&i) => edges[i].3.merge(&observation),
+                None => {
+                    edge_index.insert(key, edges.len());
+                    edges.push((rrname.clone(), target, relation_type, observation));
+                }
+            }
+        }
+    }
+    if skipped > 0 {
+        eprintln!("Warning: skipped {} pDNS records from {}", skipped, filename);
+    }
+
+    let edges = edges
+        .into_iter()
+        .map(|(source, target, relation_type, observation)| {
+            let mut properties = Properties::new();
+            properties.insert("source".to_string(), "pdns".to_string());
+            let mut edge = Edge {
+                source,
+                target,
+                relation_type: relation_type.to_string(),
+                properties,
+                ..Default::default()
+            };
+            edge.observe(&observation);
+            edge
+        })
+        .collect();
+    let mut graph = Graph { nodes, edges, ..Default::default() };
+    graph.attribute(&Provenance::new("pdns", Some(filename)));
+    Ok((graph.nodes, graph.edges))
+}
+
+fn read_jsonl(data: &str) -> Result<Vec<HashMap<String, Value>>, Box<dyn Error>> {
+    let mut rows = Vec::new();
+    for (i, line) in data.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row: HashMap<String, Value> = serde_json::from_str(line)
+            .map_err(|e| format!("Invalid pDNS JSON on line {}: {}", i + 1, e))?;
+        rows.push(row.into_iter().map(|(k, v)| (k.to_lowercase(), v)).collect());
+    }
+    Ok(rows)
+}
+
+fn read_csv(data: &str) -> Result<Vec<HashMap<String, Value>>, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(data.as_bytes());
+    let headers: Vec<String> = reader.headers()?.iter().map(|h| h.to_lowercase()).collect();
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        rows.push(
+            headers
+                .iter()
+                .cloned()
+                .zip(record.iter().map(|v| Value::String(v.to_string())))
+                .collect(),
+        );
+    }
+    Ok(rows)
+}
+
+fn lookup(row: &HashMap<String, Value>, keys: &[&str]) -> Option<String> {
+    lookup_all(row, keys).into_iter().next()
+}
+
+// Field values as strings; COF allows rdata to be a list.
+fn lookup_all(row: &HashMap<String, Value>, keys: &[&str]) -> Vec<String> {
+    let Some(value) = keys.iter().find_map(|k| row.get(*k)) else {
+        return Vec::new();
+    };
+    let values = match value {
+        Value::Array(items) => items.iter().collect(),
+        other => vec![other],
+    };
+    values
+        .into_iter()
+        .filter_map(|v| match v {
+            Value::String(s) => Some(s.trim().to_string()),
+            Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        })
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+// Epoch seconds (COF) or a textual timestamp (most CSV exports).
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(epoch) = value.parse::<i64>() {
+        return Utc.timestamp_opt(epoch, 0).single();
+    }
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S UTC"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .map(|naive| naive.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_temp(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("ugh_{}_{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn time(value: &str) -> Option<DateTime<Utc>> {
+        Some(DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc))
+    }
+
+    #[test]
+    fn test_parse_pdns_jsonl() {
+        let path = write_temp(
+            "pdns.jsonl",
+            r#"{"rrname": "evil.example.", "rrtype": "A", "rdata": "192.0.2.10", "time_first": 1609459200, "time_last": 1612137600, "count": 12}
+{"rrname": "evil.example.", "rrtype": "A", "rdata": ["192.0.2.10", "192.0.2.11"], "time_first": 1606780800, "time_last": 1610000000, "count": 3}
+{"rrname": "www.evil.example", "rrtype": "CNAME", "rdata": "evil.example."}
+{"rrname": "evil.example", "rrtype": "TXT", "rdata": "v=spf1 -all"}
+"#,
+        );
+        let (nodes, edges) = parse_pdns(&path).unwrap();
+        assert_eq!(nodes.len(), 4);
+        assert_eq!(edges.len(), 3);
+
+        let first = &edges[0];
+        assert_eq!((first.source.as_str(), first.target.as_str()), ("evil.example", "192.0.2.10"));
+        assert_eq!(first.relation_type, "resolves_to");
+        assert_eq!(first.first_seen, time("2020-12-01T00:00:00Z"));
+        assert_eq!(first.last_seen, time("2021-02-01T00:00:00Z"));
+        assert_eq!(first.count, Some(15));
+        assert_eq!(nodes[0].first_seen, time("2020-12-01T00:00:00Z"));
+        assert_eq!(nodes[0].count, None);
+        assert!(edges.iter().any(|e| e.relation_type == "cname_of" && e.target == "evil.example"));
+    }
+
+    #[test]
+    fn test_parse_pdns_csv() {
+        let path = write_temp(
+            "pdns.csv",
+            "Query,Answer,First_Seen,Last_Seen\n\
+             evil.example,192.0.2.10,2021-01-01 00:00:00,2021-03-01T12:00:00Z\n\
+             evil.example,2001:db8::1,2021-01-02 00:00:00,2021-01-03 00:00:00\n\
+             ,192.0.2.99,,\n",
+        );
+        let (nodes, edges) = parse_pdns(&path).unwrap();
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(edges.len(), 2);
+        assert_eq!(edges[0].last_seen, time("2021-03-01T12:00:00Z"));
+        assert_eq!(edges[1].first_seen, time("2021-01-02T00:00:00Z"));
+        assert!(nodes.iter().any(|n| n.label == "2001:db8::1" && n.node_type == "ip"));
+    }
+}
@@ -1,17 +1,36 @@
+use crate::bulk::{enrich_bulk, read_indicators, DEFAULT_CONCURRENCY};
+use crate::diff::{apply_patch, diff_graphs, GraphPatch};
+use crate::enrich::{enrich_graph, enrich_selected, EnrichmentStatus, NodeSelection, Status, VTEnricher};
+use crate::geoip::GeoIpEnricher;
 use crate::graphml_parser::parse_graphml;
 use crate::input_type::is_file;
-use crate::types::Graph;
+use crate::pdns_parser::parse_pdns;
+use crate::types::{Graph, GraphMetadata};
 use crate::vt_api::VTClient;
 use crate::vt_parser::parse_vt_json;
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::Serialize;
+use std::env;
 use std::error::Error;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::BufWriter;
+use std::path::Path;
 
 pub async fn transform_graph(
     input: &str,
     mode: &str,
     output_path: &str,
+) -> Result<(), Box<dyn Error>> {
+    transform_graph_with(input, mode, output_path, describe(input, mode)).await
+}
+
+// Like `transform_graph`, with the caller's own header for the result (e.g.
+// the web server naming a graph after the uploaded file).
+pub async fn transform_graph_with(
+    input: &str,
+    mode: &str,
+    output_path: &str,
+    metadata: GraphMetadata,
 ) -> Result<(), Box<dyn Error>> {
     let (nodes, edges) = if mode == "auto" {
         if is_file(input) {
@@ -19,6 +38,8 @@ pub async fn transform_graph(
                 parse_graphml(input)?
             } else if input.ends_with(".json") {
                 parse_vt_json(input)?
+            } else if input.ends_with(".csv") || input.ends_with(".jsonl") {
+                parse_pdns(input)?
             } else {
                 return Err("Auto could not identify".into());
             }
@@ -27,26 +48,192 @@ pub async fn transform_graph(
         }
     } else if mode == "graphml" || mode == "maltego" {
         parse_graphml(input)?
+    } else if mode == "pdns" {
+        parse_pdns(input)?
     } else if mode == "vtapi" {
-        // let client = VTClient::new();
-        // let vt_data = client.call_vt_hal(input, None);
-        // Intermediate async function
-        let client = VTClient::new();
-        let vt_data = client.call_vt_hal(input, None).await?;
-
-        // println!("{:?}", vt_data);
-        parse_vt_json(&vt_data)?
+        // A single indicator goes through the same enricher as vtbulk.
+        let enricher = VTEnricher::new(VTClient::new());
+        let (graph, report) = enrich_bulk(&enricher, vec![input.to_string()], 1).await;
+        if let Some(error) = report.into_iter().find_map(|r| r.error) {
+            return Err(error.into());
+        }
+        (graph.nodes, graph.edges)
+    } else if mode == "vtbulk" {
+        let enricher = VTEnricher::new(VTClient::new());
+        let indicators = read_indicators(input)?;
+        let (graph, report) = enrich_bulk(&enricher, indicators, bulk_concurrency()).await;
+
+        let failed = report.iter().filter(|r| r.status == Status::Failed).count();
+        println!("Enriched {} indicators, {} failed", report.len(), failed);
+        save_json(&report, &status_report_path(output_path))?;
+
+        (graph.nodes, graph.edges)
+    } else if mode == "vtenrich" {
+        enrich_existing_graph(input, &NodeSelection::default(), output_path).await?;
+        return Ok(());
     } else {
         return Err("Unsupported mode".into());
     };
+    let mut metadata = metadata;
+    if mode == "vtapi" || mode == "vtbulk" {
+        metadata.add_source("virustotal");
+    }
 
-    let rich_graph = Graph { nodes, edges };
+    let mut rich_graph = Graph { metadata: Some(metadata), nodes, edges };
+    add_geoip(&mut rich_graph).await?;
 
     save_json(&rich_graph, output_path)?;
 
     Ok(())
 }
 
+// Default header for a graph built from `input`: named after the input file
+// (or the indicator itself), which is also recorded as its source.
+pub fn describe(input: &str, mode: &str) -> GraphMetadata {
+    let (name, source) = if is_file(input) {
+        let name = Path::new(input).file_stem().and_then(|s| s.to_str()).unwrap_or(input);
+        (name.to_string(), input.to_string())
+    } else if mode == "vtapi" {
+        (input.to_string(), format!("virustotal:{}", input))
+    } else {
+        ("inline".to_string(), "inline json".to_string())
+    };
+    let mut metadata = GraphMetadata::new(&name, vec![source]);
+    metadata.description = Some(format!("{} transform of {}", mode, name));
+    metadata
+}
+
+// Loads any graph the transformer understands: GraphML/Maltego exports, a
+// graph JSON written by this tool, or a VT intermediate JSON. Uploads without
+// a usable extension are recognised by their first character.
+pub fn load_graph(input: &str) -> Result<Graph, Box<dyn Error>> {
+    let data = fs::read_to_string(input)?;
+    let is_xml = input.ends_with(".graphml")
+        || input.ends_with(".maltego")
+        || (!input.ends_with(".json") && data.trim_start().starts_with('<'));
+    if is_xml {
+        let (nodes, edges) = parse_graphml(input)?;
+        return Ok(Graph { nodes, edges, ..Default::default() });
+    }
+    match serde_json::from_str::<Graph>(&data) {
+        Ok(graph) => Ok(graph),
+        Err(_) => {
+            let (nodes, edges) = parse_vt_json(&data)?;
+            Ok(Graph { nodes, edges, ..Default::default() })
+        }
+    }
+}
+
+// Enriches the selected nodes of an existing graph through VirusTotal and
+// writes the merged graph plus a per-node status report.
+pub async fn enrich_existing_graph(
+    input: &str,
+    selection: &NodeSelection,
+    output_path: &str,
+) -> Result<Vec<EnrichmentStatus>, Box<dyn Error>> {
+    let mut graph = load_graph(input)?;
+    graph
+        .metadata
+        .get_or_insert_with(|| describe(input, "vtenrich"))
+        .add_source("virustotal");
+    let enricher = VTEnricher::new(VTClient::new());
+    let report = enrich_selected(&mut graph, &[&enricher], selection, bulk_concurrency()).await;
+
+    let failed = report.iter().filter(|r| r.status == Status::Failed).count();
+    println!("Enriched {} nodes, {} failed", report.len(), failed);
+    add_geoip(&mut graph).await?;
+    save_json(&graph, output_path)?;
+    save_json(&report, &status_report_path(output_path))?;
+    Ok(report)
+}
+
+// Writes the part of an existing graph that was observed during `window`.
+pub fn window_graph(input: &str, window: &str, output_path: &str) -> Result<Graph, Box<dyn Error>> {
+    let (from, to) = parse_window(window)?;
+    let graph = load_graph(input)?.as_of(from, to);
+    println!("{} nodes and {} edges in window {}", graph.nodes.len(), graph.edges.len(), window);
+    save_json(&graph, output_path)?;
+    Ok(graph)
+}
+
+// Compares two snapshots of a graph, prints what changed and writes the
+// patch that turns `old_input` into `new_input`.
+pub fn diff_graph_files(old_input: &str, new_input: &str, patch_path: &str) -> Result<GraphPatch, Box<dyn Error>> {
+    let patch = diff_graphs(&load_graph(old_input)?, &load_graph(new_input)?);
+    println!("{}", patch);
+    save_json(&patch, patch_path)?;
+    Ok(patch)
+}
+
+// Applies a patch written by `diff_graph_files` to the older graph.
+pub fn patch_graph_file(input: &str, patch_path: &str, output_path: &str) -> Result<Graph, Box<dyn Error>> {
+    let mut graph = load_graph(input)?;
+    let patch: GraphPatch = serde_json::from_str(&fs::read_to_string(patch_path)?)?;
+    apply_patch(&mut graph, &patch)?;
+    save_json(&graph, output_path)?;
+    Ok(graph)
+}
+
+// rich.json -> rich.patch.json
+pub fn patch_path(output_path: &str) -> String {
+    match output_path.strip_suffix(".json") {
+        Some(stem) => format!("{}.patch.json", stem),
+        None => format!("{}.patch.json", output_path),
+    }
+}
+
+// Open-ended bounds of a time window.
+pub type Window = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+// "2021-01-01..2021-06-30", either side may be left open ("..2021-06-30").
+// Dates cover the whole day; full RFC 3339 timestamps are taken as is.
+pub fn parse_window(window: &str) -> Result<Window, Box<dyn Error>> {
+    let (from, to) = window
+        .split_once("..")
+        .ok_or_else(|| format!("Invalid window {}, expected <from>..<to>", window))?;
+    let bound = |value: &str, end_of_day: bool| -> Result<Option<DateTime<Utc>>, Box<dyn Error>> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Ok(None);
+        }
+        if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+            return Ok(Some(datetime.with_timezone(&Utc)));
+        }
+        let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| format!("Invalid window bound {}", value))?;
+        let time = if end_of_day { date.and_hms_opt(23, 59, 59) } else { date.and_hms_opt(0, 0, 0) };
+        Ok(time.map(|t| t.and_utc()))
+    };
+    Ok((bound(from, false)?, bound(to, true)?))
+}
+
+// Local GeoIP/ASN databases cost nothing to query, so every graph gets them
+// when they are configured.
+async fn add_geoip(graph: &mut Graph) -> Result<(), Box<dyn Error>> {
+    let geoip = GeoIpEnricher::from_env()?;
+    if let Some(geoip) = geoip {
+        enrich_graph(graph, &[&geoip], DEFAULT_CONCURRENCY).await;
+        if let Some(metadata) = &mut graph.metadata {
+            metadata.add_source("geoip");
+        }
+    }
+    Ok(())
+}
+
+fn bulk_concurrency() -> usize {
+    env::var("VT_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_CONCURRENCY)
+}
+
+// rich.json -> rich.status.json
+pub fn status_report_path(output_path: &str) -> String {
+    match output_path.strip_suffix(".json") {
+        Some(stem) => format!("{}.status.json", stem),
+        None => format!("{}.status.json", output_path),
+    }
+}
+
 pub fn save_json<T: Serialize>(data: &T, filename: &str) -> Result<(), Box<dyn Error>> {
     let file = File::create(filename)?;
     let writer = BufWriter::new(file);
@@ -58,9 +245,48 @@ pub fn save_json<T: Serialize>(data: &T, filename: &str) -> Result<(), Box<dyn E
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_load_graph_formats() {
+        let graph = load_graph("../web_server/uploads/1caf50f1-286a-4d7f-9ed6-2a02c74a9ea9-netsupp_2806.graphml").unwrap();
+        assert_eq!(graph.nodes.len(), 78);
+
+        let path = std::env::temp_dir().join(format!("ugh_upload_{}", std::process::id()));
+        fs::copy("../web_server/uploads/1caf50f1-286a-4d7f-9ed6-2a02c74a9ea9-netsupp_2806.graphml", &path).unwrap();
+        assert_eq!(load_graph(path.to_str().unwrap()).unwrap().nodes.len(), 78);
+
+        save_json(&graph, path.to_str().unwrap()).unwrap();
+        assert_eq!(load_graph(path.to_str().unwrap()).unwrap().edges.len(), graph.edges.len());
+    }
+
+    #[test]
+    fn test_parse_window() {
+        let (from, to) = parse_window("2021-01-01..2021-06-30").unwrap();
+        assert_eq!(from.unwrap().to_rfc3339(), "2021-01-01T00:00:00+00:00");
+        assert_eq!(to.unwrap().to_rfc3339(), "2021-06-30T23:59:59+00:00");
+
+        let (from, to) = parse_window("..2021-06-30T12:00:00+02:00").unwrap();
+        assert_eq!(from, None);
+        assert_eq!(to.unwrap().to_rfc3339(), "2021-06-30T10:00:00+00:00");
+        assert!(parse_window("2021-01-01").is_err());
+        assert!(parse_window("yesterday..").is_err());
+    }
+
     #[tokio::test] // Or #[async_std::test] if you are using async-std
     async fn test_transform_graph() {
-        let result = transform_graph("../example_data/netsupp_2806.graphml", "auto").await;
-        assert_eq!(result.is_ok(), true);
+        let result = transform_graph("../web_server/uploads/1caf50f1-286a-4d7f-9ed6-2a02c74a9ea9-netsupp_2806.graphml", "auto", "rich.json").await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_transform_graph_metadata() {
+        let path = std::env::temp_dir().join(format!("ugh_meta_{}.json", std::process::id()));
+        let output = path.to_str().unwrap();
+        transform_graph("../web_server/uploads/1caf50f1-286a-4d7f-9ed6-2a02c74a9ea9-netsupp_2806.graphml", "auto", output).await.unwrap();
+
+        let metadata = load_graph(output).unwrap().metadata.unwrap();
+        assert_eq!(metadata.name.as_deref(), Some("1caf50f1-286a-4d7f-9ed6-2a02c74a9ea9-netsupp_2806"));
+        assert_eq!(metadata.sources, vec!["../web_server/uploads/1caf50f1-286a-4d7f-9ed6-2a02c74a9ea9-netsupp_2806.graphml"]);
+        assert!(metadata.created.is_some());
+        assert!(metadata.tool_version.unwrap().starts_with("universal_graph_transformer "));
     }
 }
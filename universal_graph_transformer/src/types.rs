@@ -1,24 +1,188 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::str::FromStr;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Node {
     pub id: Option<i32>,
     pub label: String,
     #[serde(rename = "type")]
     pub node_type: String,
     pub properties: Properties,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_seen: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub provenance: Vec<Provenance>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Edge {
     pub source: String,
     pub target: String,
     pub relation_type: String,
     pub properties: Properties,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_seen: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub provenance: Vec<Provenance>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+// Who asserted a node or edge and when: the parser, file, URL, VT endpoint
+// or LLM call it came from. `properties` lists the property keys whose
+// current value this source supplied.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Provenance {
+    pub source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    pub retrieved_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<String>,
+}
+
+impl Provenance {
+    pub fn new(source: &str, reference: Option<&str>) -> Self {
+        Provenance {
+            source: source.to_string(),
+            reference: reference.map(str::to_string),
+            retrieved_at: Utc::now(),
+            confidence: None,
+            properties: Vec::new(),
+        }
+    }
+
+    pub fn at(mut self, retrieved_at: DateTime<Utc>) -> Self {
+        self.retrieved_at = retrieved_at;
+        self
+    }
+
+    pub fn with_confidence(mut self, confidence: f64) -> Self {
+        self.confidence = Some(confidence);
+        self
+    }
+
+    fn same_retrieval(&self, other: &Provenance) -> bool {
+        self.source == other.source && self.reference == other.reference && self.retrieved_at == other.retrieved_at
+    }
+}
+
+// When something was observed: the window it was seen in and how often.
+// Missing bounds mean the source did not say, not "forever".
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Observation {
+    pub first_seen: Option<DateTime<Utc>>,
+    pub last_seen: Option<DateTime<Utc>>,
+    pub count: Option<u64>,
+}
+
+impl Observation {
+    pub fn at(time: DateTime<Utc>) -> Self {
+        Observation {
+            first_seen: Some(time),
+            last_seen: Some(time),
+            count: None,
+        }
+    }
+
+    // Widens this window to cover `other` and adds up the counts.
+    pub fn merge(&mut self, other: &Observation) {
+        self.first_seen = match (self.first_seen, other.first_seen) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.last_seen = match (self.last_seen, other.last_seen) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+        self.count = match (self.count, other.count) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+    }
+
+    // Untimed observations overlap every window; a window bound that is
+    // None is open-ended.
+    pub fn overlaps(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> bool {
+        let starts_in_time = match (to, self.first_seen.or(self.last_seen)) {
+            (Some(to), Some(first)) => first <= to,
+            _ => true,
+        };
+        let ends_in_time = match (from, self.last_seen.or(self.first_seen)) {
+            (Some(from), Some(last)) => last >= from,
+            _ => true,
+        };
+        starts_in_time && ends_in_time
+    }
+}
+
+macro_rules! impl_observed {
+    ($t:ty) => {
+        impl $t {
+            pub fn observation(&self) -> Observation {
+                Observation {
+                    first_seen: self.first_seen,
+                    last_seen: self.last_seen,
+                    count: self.count,
+                }
+            }
+
+            pub fn observe(&mut self, observation: &Observation) {
+                let mut merged = self.observation();
+                merged.merge(observation);
+                self.first_seen = merged.first_seen;
+                self.last_seen = merged.last_seen;
+                self.count = merged.count;
+            }
+
+            // Records `provenance` as the source of this element and of all
+            // its current properties.
+            pub fn attribute(&mut self, provenance: &Provenance) {
+                let mut provenance = provenance.clone();
+                provenance.properties = self.properties.other.keys().cloned().collect();
+                provenance.properties.sort();
+                self.add_provenance(provenance);
+            }
+
+            // Keeps one entry per retrieval; repeated claims from the same
+            // one are folded together.
+            pub fn add_provenance(&mut self, provenance: Provenance) {
+                match self.provenance.iter_mut().find(|p| p.same_retrieval(&provenance)) {
+                    Some(existing) => {
+                        for key in provenance.properties {
+                            if !existing.properties.contains(&key) {
+                                existing.properties.push(key);
+                            }
+                        }
+                    }
+                    None => self.provenance.push(provenance),
+                }
+            }
+
+            // The sources that supplied the current value of property `key`.
+            pub fn property_sources(&self, key: &str) -> Vec<&Provenance> {
+                self.provenance.iter().filter(|p| p.properties.iter().any(|k| k == key)).collect()
+            }
+        }
+    };
+}
+
+impl_observed!(Node);
+impl_observed!(Edge);
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct Properties {
     pub id: Option<i32>,
     #[serde(flatten)]
@@ -27,10 +191,231 @@ pub struct Properties {
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Graph {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<GraphMetadata>,
     pub nodes: Vec<Node>,
     pub edges: Vec<Edge>,
 }
 
+// Header of a saved graph so the file says what it is, what it was built
+// from, by which tool and how it may be shared.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct GraphMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tlp: Option<Tlp>,
+}
+
+impl GraphMetadata {
+    // Metadata for a graph built now by this tool. The TLP is taken from
+    // GRAPH_TLP when set.
+    pub fn new(name: &str, sources: Vec<String>) -> Self {
+        GraphMetadata {
+            name: Some(name.to_string()),
+            description: None,
+            created: Some(Utc::now()),
+            sources,
+            tool_version: Some(concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")).to_string()),
+            tlp: Tlp::from_env(),
+        }
+    }
+
+    pub fn add_source(&mut self, source: &str) {
+        if !self.sources.iter().any(|s| s == source) {
+            self.sources.push(source.to_string());
+        }
+    }
+}
+
+// Traffic Light Protocol 2.0 labels, ordered from least to most restrictive.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Tlp {
+    #[serde(rename = "TLP:CLEAR")]
+    Clear,
+    #[serde(rename = "TLP:GREEN")]
+    Green,
+    #[serde(rename = "TLP:AMBER")]
+    Amber,
+    #[serde(rename = "TLP:AMBER+STRICT")]
+    AmberStrict,
+    #[serde(rename = "TLP:RED")]
+    Red,
+}
+
+impl Tlp {
+    pub fn from_env() -> Option<Tlp> {
+        let value = env::var("GRAPH_TLP").ok().filter(|v| !v.trim().is_empty())?;
+        match value.parse() {
+            Ok(tlp) => Some(tlp),
+            Err(e) => {
+                eprintln!("Warning: {}", e);
+                None
+            }
+        }
+    }
+}
+
+// Accepts "amber", "TLP:AMBER", "tlp:amber+strict" and TLP 1.0's "white".
+impl FromStr for Tlp {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let label = value.trim().to_lowercase();
+        match label.strip_prefix("tlp:").unwrap_or(&label) {
+            "clear" | "white" => Ok(Tlp::Clear),
+            "green" => Ok(Tlp::Green),
+            "amber" => Ok(Tlp::Amber),
+            "amber+strict" => Ok(Tlp::AmberStrict),
+            "red" => Ok(Tlp::Red),
+            _ => Err(format!("unknown TLP label {}", value)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Tlp {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Display for Tlp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let label = match self {
+            Tlp::Clear => "TLP:CLEAR",
+            Tlp::Green => "TLP:GREEN",
+            Tlp::Amber => "TLP:AMBER",
+            Tlp::AmberStrict => "TLP:AMBER+STRICT",
+            Tlp::Red => "TLP:RED",
+        };
+        f.write_str(label)
+    }
+}
+
+impl Graph {
+    // Stamps every node and edge that does not say where it came from yet.
+    pub fn attribute(&mut self, provenance: &Provenance) {
+        for node in self.nodes.iter_mut().filter(|n| n.provenance.is_empty()) {
+            node.attribute(provenance);
+        }
+        for edge in self.edges.iter_mut().filter(|e| e.provenance.is_empty()) {
+            edge.attribute(provenance);
+        }
+    }
+
+    // Folds `other` into this graph. Nodes with the same label are collapsed
+    // into one (first type wins unless it is unknown, missing properties are
+    // filled in) and identical source/target/relation edges are kept once.
+    // Provenance of both sides is kept, but a source is only credited with
+    // the property values that survived.
+    //
+    // This is only meant for enrichment results folded into the graph being
+    // enriched (vtenrich, vtbulk): their labels are the normalized values
+    // `indicator::classify` and the VT parser produce, so a label is the
+    // entity, and the graph being enriched keeps its values. Graphs from
+    // separate runs or tools should be combined with `merger::merge_all`,
+    // which has identity keys, normalization and conflict policies (the
+    // merger depends on this crate, so it cannot be used from here).
+    pub fn absorb(&mut self, other: Graph) {
+        let mut index: HashMap<String, usize> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.label.clone(), i))
+            .collect();
+        let mut edge_index: HashMap<(String, String, String), usize> = self
+            .edges
+            .iter()
+            .enumerate()
+            .map(|(i, e)| ((e.source.clone(), e.target.clone(), e.relation_type.clone()), i))
+            .collect();
+
+        for node in other.nodes {
+            match index.get(&node.label) {
+                Some(&i) => {
+                    let existing = &mut self.nodes[i];
+                    existing.observe(&node.observation());
+                    if existing.node_type == "unknown" {
+                        existing.node_type = node.node_type;
+                    }
+                    let mut adopted = Vec::new();
+                    for (key, value) in node.properties {
+                        if !existing.properties.other.contains_key(&key) {
+                            existing.properties.insert(key.clone(), value);
+                            adopted.push(key);
+                        }
+                    }
+                    for mut provenance in node.provenance {
+                        provenance.properties.retain(|k| adopted.contains(k));
+                        existing.add_provenance(provenance);
+                    }
+                }
+                None => {
+                    index.insert(node.label.clone(), self.nodes.len());
+                    self.nodes.push(node);
+                }
+            }
+        }
+
+        for edge in other.edges {
+            let key = (edge.source.clone(), edge.target.clone(), edge.relation_type.clone());
+            match edge_index.get(&key) {
+                Some(&i) => {
+                    let existing = &mut self.edges[i];
+                    existing.observe(&edge.observation());
+                    for mut provenance in edge.provenance {
+                        provenance.properties.clear();
+                        existing.add_provenance(provenance);
+                    }
+                }
+                None => {
+                    edge_index.insert(key, self.edges.len());
+                    self.edges.push(edge);
+                }
+            }
+        }
+    }
+
+    // The graph as it looked during [from, to]: timed nodes and edges that
+    // were not seen in the window are dropped, as are edges left without an
+    // endpoint. Untimed nodes and edges are kept since nothing says they
+    // did not hold at the time. Edge endpoints name a node by label or, as
+    // in GraphML and Maltego exports, by id ("42" or "n42").
+    pub fn as_of(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Graph {
+        let kept: Vec<bool> = self.nodes.iter().map(|n| n.observation().overlaps(from, to)).collect();
+        let mut endpoints: HashMap<String, usize> = HashMap::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            if let Some(id) = node.id.or(node.properties.id) {
+                endpoints.entry(id.to_string()).or_insert(i);
+                endpoints.entry(format!("n{}", id)).or_insert(i);
+            }
+        }
+        // Labels win over ids.
+        for (i, node) in self.nodes.iter().enumerate() {
+            endpoints.insert(node.label.clone(), i);
+        }
+        let placed = |endpoint: &str| endpoints.get(endpoint).is_some_and(|&i| kept[i]);
+
+        let nodes = self.nodes.iter().zip(&kept).filter(|(_, &k)| k).map(|(n, _)| n.clone()).collect();
+        let edges = self
+            .edges
+            .iter()
+            .filter(|e| e.observation().overlaps(from, to))
+            .filter(|e| placed(&e.source) && placed(&e.target))
+            .cloned()
+            .collect();
+        Graph { metadata: self.metadata.clone(), nodes, edges }
+    }
+}
+
 impl Properties {
     pub fn new() -> Self {
         Properties {
@@ -87,10 +472,7 @@ impl Properties {
     pub fn get_id_from_str(id: &str) -> Option<i32> {
         let mut new_id = id.replace("n", "");
         new_id = new_id.replace("e", "");
-        match new_id.parse() {
-            Ok(id) => Some(id),
-            Err(_) => None,
-        }
+        new_id.parse().ok()
     }
     pub fn remove_n_e_prefix(value: &str) -> String {
         value.replace("n", "").replace("e", "")
@@ -121,6 +503,159 @@ impl<'a> IntoIterator for &'a Properties {
 mod tests {
     use super::*;
 
+    fn node(label: &str, node_type: &str, props: &[(&str, &str)]) -> Node {
+        let mut properties = Properties::new();
+        for (k, v) in props {
+            properties.insert(k.to_string(), v.to_string());
+        }
+        Node { label: label.to_string(), node_type: node_type.to_string(), properties, ..Default::default() }
+    }
+
+    fn edge(source: &str, target: &str, relation_type: &str) -> Edge {
+        Edge {
+            source: source.to_string(),
+            target: target.to_string(),
+            relation_type: relation_type.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn date(day: &str) -> DateTime<Utc> {
+        format!("{}T00:00:00Z", day).parse().unwrap()
+    }
+
+    #[test]
+    fn test_absorb_widens_observations() {
+        let mut first = edge("a.example", "192.0.2.1", "resolves_to");
+        first.observe(&Observation { count: Some(2), ..Observation::at(date("2021-03-01")) });
+        let mut second = edge("a.example", "192.0.2.1", "resolves_to");
+        second.observe(&Observation { count: Some(5), ..Observation::at(date("2020-01-01")) });
+
+        let mut graph = Graph { nodes: Vec::new(), edges: vec![first], ..Default::default() };
+        graph.absorb(Graph { nodes: Vec::new(), edges: vec![second], ..Default::default() });
+        assert_eq!(graph.edges.len(), 1);
+        assert_eq!(graph.edges[0].first_seen, Some(date("2020-01-01")));
+        assert_eq!(graph.edges[0].last_seen, Some(date("2021-03-01")));
+        assert_eq!(graph.edges[0].count, Some(7));
+    }
+
+    #[test]
+    fn test_absorb_keeps_provenance() {
+        let maltego = Provenance::new("maltego", Some("case.graphml"));
+        let vt = Provenance::new("virustotal", Some("https://www.virustotal.com/api/v3/domains/a.example"));
+        let mut graph = Graph {
+            nodes: vec![node("a.example", "domain", &[("owner", "alice")])],
+            edges: vec![edge("a.example", "192.0.2.1", "resolves_to")],
+            ..Default::default()
+        };
+        graph.attribute(&maltego);
+        let mut other = Graph {
+            nodes: vec![node("a.example", "domain", &[("owner", "bob"), ("reputation", "-5")])],
+            edges: vec![edge("a.example", "192.0.2.1", "resolves_to")],
+            ..Default::default()
+        };
+        other.attribute(&vt);
+        graph.absorb(other);
+
+        let merged = &graph.nodes[0];
+        assert_eq!(merged.provenance.len(), 2);
+        assert_eq!(merged.property_sources("owner")[0].source, "maltego");
+        assert_eq!(merged.property_sources("owner").len(), 1);
+        assert_eq!(merged.property_sources("reputation")[0].source, "virustotal");
+        assert_eq!(graph.edges[0].provenance.len(), 2);
+
+        let json = serde_json::to_string(&graph).unwrap();
+        let reloaded: Graph = serde_json::from_str(&json).unwrap();
+        assert_eq!(reloaded.nodes[0].provenance, merged.provenance);
+    }
+
+    #[test]
+    fn test_graph_metadata() {
+        assert_eq!("amber+strict".parse::<Tlp>(), Ok(Tlp::AmberStrict));
+        assert_eq!("TLP:WHITE".parse::<Tlp>(), Ok(Tlp::Clear));
+        assert!("TLP:BLUE".parse::<Tlp>().is_err());
+        assert!(Tlp::Red > Tlp::Amber);
+
+        let mut metadata = GraphMetadata::new("case-42", vec!["case.graphml".to_string()]);
+        metadata.tlp = Some(Tlp::Amber);
+        metadata.add_source("case.graphml");
+        metadata.add_source("virustotal");
+        let graph = Graph { metadata: Some(metadata.clone()), ..Default::default() };
+        let json = serde_json::to_value(&graph).unwrap();
+        assert_eq!(json["metadata"]["tlp"], "TLP:AMBER");
+        assert_eq!(json["metadata"]["sources"].as_array().unwrap().len(), 2);
+        let reloaded: Graph = serde_json::from_value(json).unwrap();
+        assert_eq!(reloaded.metadata, Some(metadata));
+
+        let legacy: Graph = serde_json::from_str(r#"{"nodes": [], "edges": []}"#).unwrap();
+        assert!(legacy.metadata.is_none());
+    }
+
+    #[test]
+    fn test_graph_as_of() {
+        let mut old = edge("a.example", "192.0.2.1", "resolves_to");
+        old.observe(&Observation { last_seen: Some(date("2019-06-30")), ..Observation::at(date("2019-01-01")) });
+        let mut recent = edge("a.example", "192.0.2.2", "resolves_to");
+        recent.observe(&Observation::at(date("2024-05-01")));
+        let mut old_ip = node("192.0.2.1", "ip", &[]);
+        old_ip.observe(&Observation::at(date("2019-05-15")));
+        let graph = Graph {
+            nodes: vec![node("a.example", "domain", &[]), old_ip, node("192.0.2.2", "ip", &[])],
+            edges: vec![old, recent, edge("a.example", "192.0.2.2", "registered_with")],
+            ..Default::default()
+        };
+
+        let recent_view = graph.as_of(Some(date("2024-01-01")), None);
+        assert_eq!(recent_view.nodes.len(), 2);
+        assert_eq!(recent_view.edges.len(), 2);
+
+        let old_view = graph.as_of(Some(date("2019-05-01")), Some(date("2019-12-31")));
+        assert_eq!(old_view.nodes.len(), 3);
+        assert!(old_view.edges.iter().any(|e| e.target == "192.0.2.1"));
+        assert!(old_view.edges.iter().all(|e| e.relation_type != "resolves_to" || e.target != "192.0.2.2"));
+    }
+
+    #[test]
+    fn test_graph_as_of_id_endpoints() {
+        let mut domain = node("a.example", "domain", &[]);
+        domain.id = Some(1);
+        let mut old_ip = node("192.0.2.1", "ip", &[]);
+        old_ip.properties.id = Some(2);
+        old_ip.observe(&Observation { last_seen: Some(date("2019-06-30")), ..Observation::at(date("2019-01-01")) });
+        let mut new_ip = node("192.0.2.2", "ip", &[]);
+        new_ip.id = Some(3);
+        let graph = Graph {
+            nodes: vec![domain, old_ip, new_ip],
+            edges: vec![edge("n1", "n2", "resolves_to"), edge("1", "3", "resolves_to")],
+            ..Default::default()
+        };
+
+        let view = graph.as_of(Some(date("2020-01-01")), None);
+        assert_eq!(view.nodes.len(), 2);
+        assert_eq!(view.edges.len(), 1);
+        assert_eq!(view.edges[0].target, "3");
+        assert_eq!(graph.as_of(Some(date("2019-01-01")), None).edges.len(), 2);
+    }
+
+    #[test]
+    fn test_graph_absorb_collapses_shared_nodes() {
+        let mut graph = Graph {
+            nodes: vec![node("a.example", "threat", &[]), node("192.0.2.1", "ip", &[("asn", "1")])],
+            edges: vec![edge("a.example", "192.0.2.1", "resolves_to")],
+            ..Default::default()
+        };
+        graph.absorb(Graph {
+            nodes: vec![node("b.example", "threat", &[]), node("192.0.2.1", "ip", &[("asn", "2"), ("cc", "NL")])],
+            edges: vec![edge("b.example", "192.0.2.1", "resolves_to"), edge("a.example", "192.0.2.1", "resolves_to")],
+            ..Default::default()
+        });
+        assert_eq!(graph.nodes.len(), 3);
+        assert_eq!(graph.edges.len(), 2);
+        let ip = graph.nodes.iter().find(|n| n.label == "192.0.2.1").unwrap();
+        assert_eq!(ip.properties.get("asn").map(String::as_str), Some("1"));
+        assert_eq!(ip.properties.get("cc").map(String::as_str), Some("NL"));
+    }
+
     #[test]
     fn test_properties_new() {
         let props = Properties::new();
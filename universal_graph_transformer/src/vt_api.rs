@@ -1,47 +1,105 @@
 extern crate base64;
 extern crate chrono;
-extern crate regex;
 extern crate reqwest;
 extern crate serde;
 extern crate serde_json;
 
+use crate::indicator::classify;
 use crate::mysecret::get_vtapi;
+use crate::rate_limit::{RateLimiter, VT_PUBLIC_RATE_LIMIT};
+use crate::vt_cache::VTCache;
+use crate::vt_models::{collect_valid, Descriptor, Lenient, VTObject, VTRelationship};
+use crate::vt_relationships::{self, Direction};
+use crate::whois::{parse_whois, WhoisRecord};
 use base64::encode_config;
 use base64::URL_SAFE_NO_PAD;
 use chrono::{TimeZone, Utc};
-use regex::Regex;
 use reqwest::header::{HeaderMap, HeaderValue};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::env;
 use std::error::Error;
 
+pub const VT_BASE_URL: &str = "https://www.virustotal.com/api/v3";
+
 #[derive(Debug)]
 pub struct VTClient {
     client: reqwest::Client,
+    base_url: String,
     headers: HeaderMap,
     params: HashMap<&'static str, &'static str>,
+    cache: Option<VTCache>,
+    relationships: Option<Vec<String>>,
+    rate_limiter: Option<RateLimiter>,
 }
 
 impl VTClient {
     pub fn new() -> Self {
+        let base_url = env::var("VT_BASE_URL").unwrap_or_else(|_| VT_BASE_URL.to_string());
+        let rate_limit = env::var("VT_RATE_LIMIT")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(VT_PUBLIC_RATE_LIMIT);
+        let client = Self::with_config(&base_url, get_vtapi()).with_rate_limit(rate_limit);
+        match env::var("VT_RELATIONSHIPS") {
+            Ok(selection) if !selection.trim().is_empty() => client.with_relationships(
+                selection.split(',').map(|r| r.trim().to_string()).collect(),
+            ),
+            _ => client,
+        }
+    }
+
+    pub fn with_config(base_url: &str, api_key: &str) -> Self {
         let mut headers = HeaderMap::new();
-        headers.insert("x-apikey", HeaderValue::from_static(get_vtapi()));
+        match HeaderValue::from_str(api_key) {
+            Ok(value) => {
+                headers.insert("x-apikey", value);
+            }
+            Err(_) => eprintln!("Warning: VT API key contains invalid header characters"),
+        }
 
         let mut params = HashMap::new();
         params.insert("limit", "10");
 
         VTClient {
             client: reqwest::Client::new(),
-            headers: headers,
-            params: params,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            headers,
+            params,
+            cache: VTCache::from_env(),
+            relationships: None,
+            rate_limiter: None,
         }
     }
 
+    // Requests per minute across all concurrent lookups; 0 disables limiting.
+    pub fn with_rate_limit(mut self, per_minute: u32) -> Self {
+        self.rate_limiter = (per_minute > 0).then(|| RateLimiter::per_minute(per_minute));
+        self
+    }
+
+    // Restrict (or widen, with "all") the relationships requested per object
+    // type. Names that no object type supports are reported and ignored.
+    pub fn with_relationships(mut self, relationships: Vec<String>) -> Self {
+        for unknown in vt_relationships::unknown_relationships(&relationships) {
+            eprintln!("Warning: unknown VT relationship '{}' ignored", unknown);
+        }
+        self.relationships = Some(relationships);
+        self
+    }
+
+    pub fn with_cache(mut self, cache: VTCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
     pub async fn call_vt_hal(
         &self,
         indicator: &str,
         item_type: Option<&str>,
     ) -> Result<String, Box<dyn Error>> {
+        let classified = classify(indicator);
+        let indicator = classified.value.as_str();
         let item_type = match item_type {
             Some(t) => t.to_string(),
             None => self.check_string(indicator),
@@ -52,6 +110,7 @@ impl VTClient {
                 identity_and_verdict: IdentityAndVerdict {
                     threat: Threat {
                         query: indicator.to_string(),
+                        first_seen: None,
                         last_seen: None,
                         label: None,
                         family: None,
@@ -62,8 +121,11 @@ impl VTClient {
                         tags: None,
                     },
                     whois: None,
+                    whois_record: None,
                 },
                 activity_and_relationships: None,
+                warnings: Vec::new(),
+                retrieval: None,
             };
 
             return Ok(serde_json::to_string(&vt_data)?);
@@ -83,16 +145,51 @@ impl VTClient {
         };
 
         let vturl = format!(
-            "https://www.virustotal.com/api/v3/{}/{}?relationships={}",
-            item_type, id_encoded, relations
+            "{}/{}/{}?relationships={}",
+            self.base_url, item_type, id_encoded, relations
         );
 
-        let result: Value = self.query_handler(&vturl).await?;
+        let result: Value = self.cached_query(item_type, id, &relations, &vturl).await?;
+
+        let mut data = self.extract_data(result, item_type, id).await?;
+        data.retrieval = Some(Retrieval {
+            endpoint: format!("{}/{}/{}", self.base_url, item_type, id_encoded),
+            retrieved_at: Utc::now().to_rfc3339(),
+        });
+        Ok(data)
+    }
+
+    async fn cached_query(
+        &self,
+        item_type: &str,
+        id: &str,
+        relations: &str,
+        url: &str,
+    ) -> Result<Value, Box<dyn Error>> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return self.query_handler(url).await,
+        };
+
+        let key = VTCache::key(item_type, id, relations);
+        if let Some(result) = cache.get(item_type, &key) {
+            return Ok(result);
+        }
+        if cache.is_offline() {
+            return Err(format!("Offline mode: no cached response for {} {}", item_type, id).into());
+        }
 
-        self.extract_data(result, id).await
+        let result = self.query_handler(url).await?;
+        if let Err(e) = cache.put(item_type, &key, &result) {
+            eprintln!("Warning: could not cache VT response for {}: {}", id, e);
+        }
+        Ok(result)
     }
 
     async fn query_handler(&self, url: &str) -> Result<Value, Box<dyn Error>> {
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.acquire().await;
+        }
         let response = self
             .client
             .get(url)
@@ -116,73 +213,68 @@ impl VTClient {
         Ok(result)
     }
 
-    async fn extract_data(&self, object: Value, id: &str) -> Result<JsonInput, Box<dyn Error>> {
+    async fn extract_data(
+        &self,
+        object: Value,
+        item_type: &str,
+        id: &str,
+    ) -> Result<JsonInput, Box<dyn Error>> {
         let data = object.get("data").ok_or("No data field in response")?;
+        let vt_object: VTObject = serde_json::from_value(data.clone())
+            .map_err(|e| format!("Unexpected shape for VT object {}: {}", id, e))?;
+
+        let mut warnings = Vec::new();
+        let attrs = vt_object
+            .attributes
+            .and_then(|a| a.into_valid("attributes", &mut warnings))
+            .unwrap_or_default();
 
         let threat = Threat {
             query: id.to_string(),
-            last_seen: data
-                .get("attributes")
-                .and_then(|attrs| attrs.get("last_submission_date"))
-                .map(|v| Utc.timestamp_opt(v.as_i64().unwrap_or(0), 0))
-                .and_then(|local_result| match local_result {
-                    chrono::LocalResult::None => None,
-                    chrono::LocalResult::Single(datetime) => Some(datetime.to_rfc3339()),
-                    chrono::LocalResult::Ambiguous(_, _) => None,
-                }), // Handle ambiguous cases if needed
-            label: data
-                .get("attributes")
-                .and_then(|attrs| attrs.get("popular_threat_classification"))
-                .and_then(|ptc| ptc.get("suggested_threat_label"))
-                .map(|v| v.as_str().unwrap_or("").to_string()),
-            family: data
-                .get("attributes")
-                .and_then(|attrs| attrs.get("malware_config"))
+            first_seen: attrs
+                .first_submission_date
+                .and_then(|v| v.into_valid("first_submission_date", &mut warnings))
+                .and_then(epoch_to_rfc3339),
+            last_seen: attrs
+                .last_submission_date
+                .and_then(|v| v.into_valid("last_submission_date", &mut warnings))
+                .and_then(epoch_to_rfc3339),
+            label: attrs
+                .popular_threat_classification
+                .and_then(|v| v.into_valid("popular_threat_classification", &mut warnings))
+                .and_then(|ptc| ptc.suggested_threat_label),
+            family: attrs
+                .malware_config
+                .as_ref()
                 .and_then(|mc| mc.get("family"))
                 .cloned(),
             judgment: Some(Value::Array(
-                data.get("attributes")
-                    .and_then(|attrs| attrs.get("last_analysis_results"))
-                    .unwrap_or(&Value::Null)
-                    .as_object()
-                    .unwrap_or(&serde_json::Map::new())
-                    .iter()
-                    .filter(|(_, v)| {
-                        v.get("category")
-                            .map(|cat| cat == "malicious")
-                            .unwrap_or(false)
+                attrs
+                    .last_analysis_results
+                    .and_then(|v| v.into_valid("last_analysis_results", &mut warnings))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|(engine, result)| {
+                        result
+                            .into_valid("last_analysis_results", &mut warnings)
+                            .filter(|r| r.category.as_deref() == Some("malicious"))
+                            .map(|_| Value::String(engine))
                     })
-                    .map(|(k, _)| Value::String(k.clone()))
                     .collect(),
             )),
-            reputation: data
-                .get("attributes")
-                .and_then(|attrs| attrs.get("reputation"))
-                .map(|v| v.as_i64().unwrap_or(0)),
-            verdicts: data
-                .get("attributes")
-                .and_then(|attrs| attrs.get("sandbox_verdict"))
-                .cloned(),
-            jarm: data
-                .get("attributes")
-                .and_then(|attrs| attrs.get("jarn"))
-                .map(|v| v.as_str().unwrap_or("").to_string()),
-            tags: data
-                .get("attributes")
-                .and_then(|attrs| attrs.get("tags"))
-                .map(|v| {
-                    v.as_array()
-                        .unwrap()
-                        .iter()
-                        .map(|tag| tag.as_str().unwrap().to_string())
-                        .collect()
-                }),
+            reputation: attrs
+                .reputation
+                .and_then(|v| v.into_valid("reputation", &mut warnings)),
+            verdicts: attrs.sandbox_verdicts,
+            jarm: attrs.jarm.and_then(|v| v.into_valid("jarm", &mut warnings)),
+            tags: attrs
+                .tags
+                .and_then(|v| v.into_valid("tags", &mut warnings))
+                .map(|tags| collect_valid(tags, "tags", &mut warnings)),
         };
 
-        let raw_whois = data
-            .get("attributes")
-            .and_then(|attrs| attrs.get("whois"))
-            .map(|v| v.as_str().unwrap_or("").to_string());
+        let raw_whois = attrs.whois.and_then(|v| v.into_valid("whois", &mut warnings));
+        let whois_record = raw_whois.as_deref().map(parse_whois);
         let whois_details = if let Some(raw_whois) = raw_whois {
             let mut details = HashMap::new();
             for line in raw_whois.lines() {
@@ -195,108 +287,163 @@ impl VTClient {
             None
         };
 
-        let relationships = self.extract_relationships(&object).await?;
+        let related = self
+            .extract_relationships(item_type, id, vt_object.relationships, &mut warnings)
+            .await;
         let related_items = RelatedItems {
-            communicating_files: relationships.get("communicating_files").cloned(),
-            contacted_ips: relationships.get("contacted_ips").cloned(),
-            contacted_domains: relationships.get("contacted_domains").cloned(),
-            resolves_to: relationships.get("resolutions").map(|res| {
-                res.iter()
-                    .map(|r| ResolveRelationship {
-                        domain: r.clone(),
-                        ip: id.to_string(), // Assuming 'id' is the IP here. Adjust as needed.
+            communicating_files: None,
+            contacted_ips: None,
+            contacted_domains: None,
+            resolves_to: None,
+            related,
+        };
+
+        let dns = attrs
+            .last_dns_records
+            .and_then(|v| v.into_valid("last_dns_records", &mut warnings))
+            .map(|records| {
+                collect_valid(records, "last_dns_records", &mut warnings)
+                    .into_iter()
+                    .map(|record| DnsRecord {
+                        record_type: record.record_type,
+                        value: record.value,
+                        priority: record.priority,
                     })
                     .collect()
-            }),
-        };
+            });
+
+        for warning in &warnings {
+            eprintln!("Warning: VT response for {}: {}", id, warning);
+        }
 
         Ok(JsonInput {
             identity_and_verdict: IdentityAndVerdict {
-                threat: threat,
+                threat,
                 whois: whois_details,
+                whois_record,
             },
             activity_and_relationships: Some(ActivityAndRelationships {
-                related_items: related_items,
-                dns: data
-                    .get("attributes")
-                    .and_then(|attrs| attrs.get("last_dns_records"))
-                    .map(|dns| {
-                        dns.as_array()
-                            .unwrap()
-                            .iter()
-                            .map(|record| DnsRecord {
-                                record_type: record
-                                    .get("type")
-                                    .unwrap()
-                                    .as_str()
-                                    .unwrap()
-                                    .to_string(),
-                                value: record.get("value").unwrap().as_str().unwrap().to_string(),
-                            })
-                            .collect()
-                    }),
+                related_items,
+                dns,
             }),
+            warnings,
+            retrieval: None,
         })
     }
 
     async fn extract_relationships(
         &self,
-        result: &Value,
-    ) -> Result<HashMap<String, Vec<String>>, Box<dyn Error>> {
-        let mut relationships = HashMap::new();
-        let data = result
-            .get("data")
-            .ok_or("No data field in response")?
-            .get("relationships")
-            .ok_or("No relationships field in data")?;
-
-        for (key, value) in data.as_object().unwrap() {
-            let ids: Vec<String> = value
-                .get("data")
-                .unwrap()
-                .as_array()
-                .unwrap()
-                .iter()
-                .map(|entry| entry.get("id").unwrap().as_str().unwrap().to_string())
-                .collect();
-            relationships.insert(key.clone(), ids);
+        item_type: &str,
+        id: &str,
+        relationships: HashMap<String, Lenient<VTRelationship>>,
+        warnings: &mut Vec<String>,
+    ) -> Vec<RelatedObject> {
+        let mut related = Vec::new();
+
+        let mut relationships: Vec<_> = relationships.into_iter().collect();
+        relationships.sort_by(|a, b| a.0.cmp(&b.0));
+
+        for (name, value) in relationships {
+            let spec = match vt_relationships::find(item_type, &name) {
+                Some(spec) => spec,
+                None => {
+                    warnings.push(format!("{}: relationship not in catalogue for {}", name, item_type));
+                    continue;
+                }
+            };
+            let descriptors = value
+                .into_valid(&name, warnings)
+                .map(|rel| rel.into_descriptors(&name, warnings))
+                .unwrap_or_default();
+
+            for descriptor in descriptors {
+                let (related_id, node_type) = related_identity(&name, id, spec.direction, &descriptor);
+                related.push(RelatedObject {
+                    relationship: name.clone(),
+                    edge_type: spec.edge_type.to_string(),
+                    direction: spec.direction.as_str().to_string(),
+                    id: related_id,
+                    node_type,
+                    date: related_date(&descriptor),
+                });
+            }
         }
 
-        Ok(relationships)
+        related
     }
 
     fn define_relationships(&self, item_type: &str) -> String {
-        match item_type {
-            "files" => "collections,communicating_files,contacted_ips,contacted_urls,contacted_domains,itw_domains,itw_urls,itw_ips".to_string(),
-            "ip_addresses" | "domains" => "collections,communicating_files,resolutions,referrer_files,resolutions,downloaded_files".to_string(),
-            "urls" => "collections,communicating_files,referrer_files,downloaded_files".to_string(),
-            _ => {
-                println!("Warning: no relations defined for file type");
-                "".to_string()
-            },
+        if vt_relationships::catalogue(item_type).is_empty() {
+            println!("Warning: no relations defined for file type");
         }
+        vt_relationships::select(item_type, self.relationships.as_deref()).join(",")
     }
 
     fn check_string(&self, input: &str) -> String {
-        let hash_regex = Regex::new(r"^[a-fA-F0-9]{32,64}$").unwrap();
-        let ip_regex = Regex::new(r"^\d{1,3}\.\d{1,3}\.\d{1,3}\.\d{1,3}$").unwrap();
-        let domain_regex =
-            Regex::new(r"^[a-zA-Z0-9-]+(\.[a-zA-Z0-9-]+)*(\.[a-zA-Z]{2,})$").unwrap();
-        let url_regex =
-            Regex::new(r"^(http|https)://[a-zA-Z0-9-]+(\.[a-zA-Z0-9-]+)*(\.[a-zA-Z]{2,})(/.*)*$")
-                .unwrap();
-
-        if hash_regex.is_match(input) {
-            "files".to_string()
-        } else if ip_regex.is_match(input) {
-            "ip_addresses".to_string()
-        } else if domain_regex.is_match(input) {
-            "domains".to_string()
-        } else if url_regex.is_match(input) {
-            "urls".to_string()
-        } else {
-            "unknown".to_string()
-        }
+        classify(input)
+            .kind
+            .vt_collection()
+            .unwrap_or("unknown")
+            .to_string()
+    }
+}
+
+// When VT saw the relationship, for descriptors that say so (resolution
+// dates, or a date in the context attributes).
+fn related_date(descriptor: &Descriptor) -> Option<String> {
+    [&descriptor.context_attributes, &descriptor.attributes]
+        .into_iter()
+        .flatten()
+        .find_map(|attrs| attrs.get("date").and_then(Value::as_i64))
+        .and_then(epoch_to_rfc3339)
+}
+
+fn epoch_to_rfc3339(ts: i64) -> Option<String> {
+    match Utc.timestamp_opt(ts, 0) {
+        chrono::LocalResult::Single(datetime) => Some(datetime.to_rfc3339()),
+        _ => None,
+    }
+}
+
+// Resolution descriptors are identified by "<ip><domain>", so the side that is
+// not the queried object is recovered by stripping the query; URL descriptors
+// carry the URL itself in their context attributes.
+fn related_identity(
+    relationship: &str,
+    query: &str,
+    direction: Direction,
+    descriptor: &Descriptor,
+) -> (String, String) {
+    if relationship == "resolutions" {
+        return match direction {
+            Direction::Outgoing => (
+                descriptor.id.strip_suffix(query).unwrap_or(&descriptor.id).to_string(),
+                "ip".to_string(),
+            ),
+            Direction::Incoming => (
+                descriptor.id.strip_prefix(query).unwrap_or(&descriptor.id).to_string(),
+                "domain".to_string(),
+            ),
+        };
+    }
+
+    let url = descriptor
+        .context_attributes
+        .as_ref()
+        .and_then(|ctx| ctx.get("url"))
+        .and_then(|url| url.as_str());
+    match url {
+        Some(url) if descriptor.object_type == "url" => (url.to_string(), "url".to_string()),
+        _ => (
+            descriptor.id.clone(),
+            vt_relationships::node_type_for(&descriptor.object_type).to_string(),
+        ),
+    }
+}
+
+impl Default for VTClient {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -304,17 +451,32 @@ impl VTClient {
 pub struct JsonInput {
     pub identity_and_verdict: IdentityAndVerdict,
     pub activity_and_relationships: Option<ActivityAndRelationships>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retrieval: Option<Retrieval>,
+}
+
+// Where and when the object was fetched, for provenance.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Retrieval {
+    pub endpoint: String,
+    pub retrieved_at: String,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct IdentityAndVerdict {
     pub threat: Threat,
     pub whois: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub whois_record: Option<WhoisRecord>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct Threat {
     pub query: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_seen: Option<String>,
     pub last_seen: Option<String>,
     pub label: Option<String>,
     pub family: Option<Value>,
@@ -333,10 +495,28 @@ pub struct ActivityAndRelationships {
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct RelatedItems {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub communicating_files: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub contacted_ips: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub contacted_domains: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub resolves_to: Option<Vec<ResolveRelationship>>,
+    #[serde(default)]
+    pub related: Vec<RelatedObject>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct RelatedObject {
+    pub relationship: String,
+    pub edge_type: String,
+    pub direction: String,
+    pub id: String,
+    #[serde(rename = "type")]
+    pub node_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -350,16 +530,201 @@ pub struct DnsRecord {
     #[serde(rename = "type")]
     pub record_type: String,
     pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i64>,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::vt_mock::{spawn_mock_vt, MOCK_API_KEY};
+    use crate::vt_parser::parse_vt_json;
+
+    const FILE_HASH: &str = "4f2d8e1c7a9b3d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f708192a3b4c5d6";
+
+    fn mock_client() -> VTClient {
+        VTClient::with_config(&spawn_mock_vt(), MOCK_API_KEY)
+    }
+
+    #[tokio::test]
+    async fn test_call_vt_hal_offline_replay() {
+        let dir = std::env::temp_dir().join(format!("ugh_vt_offline_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = VTCache::new(&dir, None).with_offline(true);
+        let vt = VTClient::with_config("http://127.0.0.1:9", MOCK_API_KEY).with_cache(cache.clone());
+
+        let missing = vt.call_vt_hal("example.com", Some("domains")).await;
+        assert!(missing.is_err());
+
+        let relations = vt.define_relationships("domains");
+        let key = VTCache::key("domains", "example.com", &relations);
+        let response = serde_json::json!({
+            "data": {
+                "id": "example.com",
+                "attributes": {"reputation": 5, "tags": ["parked"]},
+                "relationships": {}
+            }
+        });
+        cache.put("domains", &key, &response).unwrap();
+
+        let result = vt.call_vt_hal("example.com", Some("domains")).await.unwrap();
+        let parsed: JsonInput = serde_json::from_str(&result).unwrap();
+        assert_eq!(parsed.identity_and_verdict.threat.reputation, Some(5));
+    }
+
+    #[tokio::test]
+    async fn test_mock_file_end_to_end() {
+        let vt = mock_client();
+        let result = vt.call_vt_hal(FILE_HASH, None).await.unwrap();
+        let parsed: JsonInput = serde_json::from_str(&result).unwrap();
+        let threat = &parsed.identity_and_verdict.threat;
+        assert_eq!(threat.label.as_deref(), Some("trojan.gootloader/jscript"));
+        assert_eq!(threat.reputation, Some(-12));
+        assert_eq!(threat.judgment.as_ref().unwrap().as_array().unwrap().len(), 3);
+
+        let (nodes, edges) = parse_vt_json(&result).unwrap();
+        assert_eq!(nodes[0].label, FILE_HASH);
+        assert_eq!(nodes[0].first_seen.map(|t| t.timestamp()), Some(1717200000));
+        assert_eq!(nodes[0].last_seen.map(|t| t.timestamp()), Some(1719532800));
+        assert!(edges.iter().any(|e| e.target == "198.51.100.23"));
+        assert!(edges.iter().any(|e| e.target == "example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_mock_domain_end_to_end() {
+        let vt = mock_client();
+        let result = vt.call_vt_hal("example.com", None).await.unwrap();
+        let parsed: JsonInput = serde_json::from_str(&result).unwrap();
+        let whois = parsed.identity_and_verdict.whois.as_ref().unwrap();
+        assert_eq!(whois.get("Registrar IANA ID").map(String::as_str), Some("376"));
+        let record = parsed.identity_and_verdict.whois_record.as_ref().unwrap();
+        assert_eq!(record.name_servers.len(), 2);
+
+        let (nodes, edges) = parse_vt_json(&result).unwrap();
+        assert_eq!(nodes[0].properties.get("whois_created").map(String::as_str), Some("1995-08-14T04:00:00Z"));
+        assert!(nodes.iter().any(|n| n.node_type == "registrar"));
+        assert!(nodes.iter().any(|n| n.label == "domain-admin@iana.org" && n.node_type == "email"));
+        assert!(edges.iter().any(|e| e.relation_type == "has_ns" && e.target == "b.iana-servers.net"));
+        assert!(nodes.iter().all(|n| n.node_type != "dns"));
+        assert!(nodes.iter().any(|n| n.label == "2001:db8::10" && n.node_type == "ip"));
+        assert!(edges
+            .iter()
+            .any(|e| e.relation_type == "has_mx" && e.target == "mail.example.com" && e.properties.get("priority").is_some()));
+        assert_eq!(edges.iter().filter(|e| e.relation_type == "has_ns").count(), 2);
+        assert_eq!(nodes[0].properties.get("dns_txt").map(String::as_str), Some("v=spf1 -all"));
+        assert!(edges.iter().any(|e| e.relation_type == "communicates_with" && e.target == "example.com"));
+        assert!(edges
+            .iter()
+            .any(|e| e.relation_type == "resolves_to" && e.source == "example.com" && e.target == "203.0.113.7"));
+        let dated = edges.iter().find(|e| e.relation_type == "resolves_to" && e.target == "192.0.2.10").unwrap();
+        assert_eq!(dated.first_seen.map(|t| t.timestamp()), Some(1717200000));
+        assert!(edges.iter().any(|e| e.relation_type == "refers_to"));
+    }
+
+    #[tokio::test]
+    async fn test_mock_ip_end_to_end() {
+        let vt = mock_client();
+        let result = vt.call_vt_hal("192.0.2.10", None).await.unwrap();
+        let (nodes, edges) = parse_vt_json(&result).unwrap();
+        assert_eq!(nodes[0].label, "192.0.2.10");
+        assert_eq!(edges.iter().filter(|e| e.relation_type == "resolves_to").count(), 2);
+        assert!(edges.iter().any(|e| e.source == "cdn.example.net" && e.target == "192.0.2.10"));
+        assert!(nodes.iter().any(|n| n.label == "cdn.example.net" && n.node_type == "domain"));
+    }
+
+    #[test]
+    fn test_define_relationships_selection() {
+        let vt = VTClient::with_config("http://127.0.0.1:9", MOCK_API_KEY);
+        assert_eq!(
+            vt.define_relationships("ip_addresses"),
+            "collections,communicating_files,resolutions,referrer_files,downloaded_files"
+        );
+
+        let vt = vt.with_relationships(vec!["dropped_files".to_string(), "execution_parents".to_string()]);
+        assert_eq!(vt.define_relationships("files"), "dropped_files,execution_parents");
+        assert_eq!(vt.define_relationships("domains"), "");
+    }
+
+    #[tokio::test]
+    async fn test_mock_url_end_to_end() {
+        let vt = mock_client();
+        let result = vt.call_vt_hal("http://example.com/payload.exe", None).await.unwrap();
+        let parsed: JsonInput = serde_json::from_str(&result).unwrap();
+        assert_eq!(parsed.identity_and_verdict.threat.tags, Some(vec!["downloads-pe".to_string()]));
+        assert!(parsed.warnings.is_empty());
+        let (_, edges) = parse_vt_json(&result).unwrap();
+        assert!(edges.iter().any(|e| e.relation_type == "served_from" && e.target == "192.0.2.10"));
+    }
+
+    #[tokio::test]
+    async fn test_extract_data_tolerates_schema_deviations() {
+        let vt = VTClient::with_config("http://127.0.0.1:9", MOCK_API_KEY);
+        let response = serde_json::json!({
+            "data": {
+                "id": "http://example.org/",
+                "type": "url",
+                "attributes": {
+                    "tags": "not-a-list",
+                    "reputation": 7,
+                    "last_dns_records": [
+                        {"type": "A", "value": "192.0.2.44"},
+                        {"type": "MX"},
+                        "garbage"
+                    ],
+                    "whois": null
+                },
+                "relationships": {
+                    "last_serving_ip_address": {"data": {"type": "ip_address", "id": "192.0.2.44"}},
+                    "network_location": {"data": null},
+                    "contacted_ips": {"data": [{"type": "ip_address"}, {"type": "ip_address", "id": "192.0.2.45"}]},
+                    "graphs": {"meta": {"count": 0}},
+                    "collections": "unexpected"
+                }
+            }
+        });
+
+        let parsed = vt
+            .extract_data(response, "urls", "http://example.org/")
+            .await
+            .unwrap();
+        assert_eq!(parsed.identity_and_verdict.threat.reputation, Some(7));
+        assert!(parsed.identity_and_verdict.threat.tags.is_none());
+        let activity = parsed.activity_and_relationships.unwrap();
+        assert_eq!(activity.dns.unwrap().len(), 1);
+        let related: Vec<&str> = activity.related_items.related.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(related, vec!["192.0.2.45", "192.0.2.44"]);
+        assert_eq!(parsed.warnings.len(), 6);
+
+        let missing = vt.extract_data(serde_json::json!({"meta": {}}), "urls", "http://example.org/").await;
+        assert!(missing.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mock_errors() {
+        let wrong_key = VTClient::with_config(&spawn_mock_vt(), "not-the-key");
+        let result = wrong_key.call_vt_hal(FILE_HASH, None).await;
+        assert!(result.unwrap_err().to_string().contains("Wrong API key"));
+
+        let vt = mock_client();
+        let result = vt.call_vt_hal("unknown-example.org", None).await;
+        assert!(result.unwrap_err().to_string().contains("not found"));
+    }
+
+    #[test]
+    fn test_check_string() {
+        let vt = VTClient::with_config("http://127.0.0.1:9", MOCK_API_KEY);
+        assert_eq!(vt.check_string("44d88612fea8a8f36de82e1278abb02f"), "files");
+        assert_eq!(vt.check_string("2001:db8::1"), "ip_addresses");
+        assert_eq!(vt.check_string("evil[.]example.com"), "domains");
+        assert_eq!(vt.check_string("hxxp://evil.example.com/a"), "urls");
+        assert_eq!(vt.check_string("admin@evil.example.com"), "unknown");
+    }
 
     #[tokio::test]
-    async fn test_call_vt_hal() {
-        let vt = VTClient::new();
-        let result = vt.call_vt_hal("example.com", Some("domains")).await;
-        assert!(result.is_err());
+    async fn test_call_vt_hal_refangs_input() {
+        let vt = mock_client();
+        let result = vt.call_vt_hal("Example[.]COM", None).await.unwrap();
+        let parsed: JsonInput = serde_json::from_str(&result).unwrap();
+        assert_eq!(parsed.identity_and_verdict.threat.query, "example.com");
     }
 }
@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_CACHE_DIR: &str = ".vt_cache";
+pub const DEFAULT_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+// On-disk cache of raw VirusTotal responses, one JSON file per
+// endpoint + indicator + relationships combination.
+#[derive(Debug, Clone)]
+pub struct VTCache {
+    dir: PathBuf,
+    ttl: Option<Duration>,
+    endpoint_ttls: HashMap<String, Duration>,
+    offline: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    key: String,
+    fetched_at: u64,
+    response: Value,
+}
+
+impl VTCache {
+    pub fn new(dir: impl Into<PathBuf>, ttl: Option<Duration>) -> Self {
+        VTCache {
+            dir: dir.into(),
+            ttl,
+            endpoint_ttls: HashMap::new(),
+            offline: false,
+        }
+    }
+
+    // VT_CACHE_DIR enables the cache, VT_CACHE_TTL sets the default TTL in
+    // seconds (0 disables expiry) and VT_OFFLINE=1 serves only from cache.
+    pub fn from_env() -> Option<Self> {
+        let offline = env::var("VT_OFFLINE")
+            .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        let dir = match env::var("VT_CACHE_DIR") {
+            Ok(dir) => dir,
+            Err(_) if offline => DEFAULT_CACHE_DIR.to_string(),
+            Err(_) => return None,
+        };
+        let ttl = match env::var("VT_CACHE_TTL").ok().and_then(|v| v.parse::<u64>().ok()) {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => Some(Duration::from_secs(DEFAULT_TTL_SECS)),
+        };
+        Some(VTCache::new(dir, ttl).with_offline(offline))
+    }
+
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    // Override the default TTL for one endpoint, e.g. keep "files" longer
+    // than "domains" whose resolutions change often.
+    pub fn with_endpoint_ttl(mut self, endpoint: &str, ttl: Duration) -> Self {
+        self.endpoint_ttls.insert(endpoint.to_string(), ttl);
+        self
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    pub fn key(endpoint: &str, indicator: &str, relationships: &str) -> String {
+        format!("{}|{}|{}", endpoint, indicator.trim(), relationships)
+    }
+
+    // Returns the cached response if present and fresh. Offline mode ignores
+    // expiry so an investigation can be replayed as it was recorded.
+    pub fn get(&self, endpoint: &str, key: &str) -> Option<Value> {
+        let data = fs::read_to_string(self.path_for(endpoint, key)).ok()?;
+        let entry: CacheEntry = serde_json::from_str(&data).ok()?;
+        if entry.key != key {
+            return None;
+        }
+        if !self.offline && !self.is_fresh(endpoint, entry.fetched_at) {
+            return None;
+        }
+        Some(entry.response)
+    }
+
+    pub fn put(&self, endpoint: &str, key: &str, response: &Value) -> Result<(), Box<dyn Error>> {
+        let path = self.path_for(endpoint, key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let entry = CacheEntry {
+            key: key.to_string(),
+            fetched_at: now_secs(),
+            response: response.clone(),
+        };
+        fs::write(path, serde_json::to_string_pretty(&entry)?)?;
+        Ok(())
+    }
+
+    fn is_fresh(&self, endpoint: &str, fetched_at: u64) -> bool {
+        let ttl = self.endpoint_ttls.get(endpoint).copied().or(self.ttl);
+        match ttl {
+            Some(ttl) => now_secs().saturating_sub(fetched_at) < ttl.as_secs(),
+            None => true,
+        }
+    }
+
+    fn path_for(&self, endpoint: &str, key: &str) -> PathBuf {
+        self.dir.join(endpoint).join(format!("{:016x}.json", fnv1a(key)))
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Stable across builds, unlike std's DefaultHasher, so cache files stay valid.
+fn fnv1a(input: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in input.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn temp_cache_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("ugh_vt_cache_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let cache = VTCache::new(temp_cache_dir("round_trip"), None);
+        let key = VTCache::key("domains", "example.com", "resolutions");
+        cache.put("domains", &key, &json!({"data": {"id": "example.com"}})).unwrap();
+        assert_eq!(cache.get("domains", &key).unwrap()["data"]["id"], "example.com");
+        assert!(cache.get("domains", &VTCache::key("domains", "example.com", "")).is_none());
+    }
+
+    #[test]
+    fn test_cache_expired_entry() {
+        let dir = temp_cache_dir("expired");
+        let cache = VTCache::new(&dir, Some(Duration::from_secs(3600)))
+            .with_endpoint_ttl("domains", Duration::ZERO);
+        let key = VTCache::key("domains", "example.com", "");
+        cache.put("domains", &key, &json!({"data": {}})).unwrap();
+        assert!(cache.get("domains", &key).is_none());
+
+        let offline = VTCache::new(&dir, Some(Duration::ZERO)).with_offline(true);
+        assert!(offline.get("domains", &key).is_some());
+    }
+}
@@ -0,0 +1,82 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::thread;
+
+pub const MOCK_API_KEY: &str = "mock-vt-key";
+
+// Minimal stand-in for the VirusTotal v3 API. Serves recorded responses from
+// fixtures/vt/<collection>/<id>.json and answers like VT for unknown objects
+// or a wrong API key. Returns the base URL to hand to VTClient::with_config.
+pub fn spawn_mock_vt() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind mock VT server");
+    let addr = listener.local_addr().expect("Mock VT server has no address");
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || handle_request(stream));
+        }
+    });
+    format!("http://{}/api/v3", addr)
+}
+
+fn handle_request(mut stream: TcpStream) {
+    let mut reader = match stream.try_clone() {
+        Ok(s) => BufReader::new(s),
+        Err(_) => return,
+    };
+
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let path = request_line.split_whitespace().nth(1).unwrap_or("").to_string();
+
+    let mut api_key = String::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).is_err() || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("x-apikey") {
+                api_key = value.trim().to_string();
+            }
+        }
+    }
+
+    let (status, body) = if api_key != MOCK_API_KEY {
+        ("401 Unauthorized", vt_error("WrongCredentialsError", "Wrong API key"))
+    } else {
+        match fixture_for(&path).and_then(|p| fs::read_to_string(p).ok()) {
+            Some(body) => ("200 OK", body),
+            None => ("404 Not Found", vt_error("NotFoundError", &format!("{} not found", path))),
+        }
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes());
+}
+
+fn fixture_for(path: &str) -> Option<PathBuf> {
+    let path = path.split('?').next()?;
+    let rest = path.strip_prefix("/api/v3/")?;
+    let (collection, id) = rest.split_once('/')?;
+    if id.contains('/') || id.contains("..") {
+        return None;
+    }
+    Some(fixtures_dir().join(collection).join(format!("{}.json", id)))
+}
+
+pub fn fixtures_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures").join("vt")
+}
+
+fn vt_error(code: &str, message: &str) -> String {
+    serde_json::json!({"error": {"code": code, "message": message}}).to_string()
+}
@@ -0,0 +1,170 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+// Typed views of the VirusTotal v3 object responses. Every field that VT has
+// been seen to omit or reshape is wrapped in `Lenient`, so a surprise in one
+// field degrades to a warning instead of failing the whole lookup.
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Lenient<T> {
+    Valid(T),
+    Invalid(Value),
+}
+
+impl<T> Lenient<T> {
+    pub fn into_valid(self, field: &str, warnings: &mut Vec<String>) -> Option<T> {
+        match self {
+            Lenient::Valid(value) => Some(value),
+            Lenient::Invalid(Value::Null) => None,
+            Lenient::Invalid(value) => {
+                warnings.push(format!("{}: unexpected value {}", field, preview(&value)));
+                None
+            }
+        }
+    }
+}
+
+pub fn collect_valid<T>(items: Vec<Lenient<T>>, field: &str, warnings: &mut Vec<String>) -> Vec<T> {
+    items
+        .into_iter()
+        .filter_map(|item| item.into_valid(field, warnings))
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VTObject {
+    #[serde(default)]
+    pub id: String,
+    #[serde(rename = "type", default)]
+    pub object_type: String,
+    pub attributes: Option<Lenient<VTAttributes>>,
+    #[serde(default)]
+    pub relationships: HashMap<String, Lenient<VTRelationship>>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct VTAttributes {
+    pub first_submission_date: Option<Lenient<i64>>,
+    pub last_submission_date: Option<Lenient<i64>>,
+    pub popular_threat_classification: Option<Lenient<PopularThreatClassification>>,
+    pub malware_config: Option<Value>,
+    pub last_analysis_results: Option<Lenient<HashMap<String, Lenient<AnalysisResult>>>>,
+    pub reputation: Option<Lenient<i64>>,
+    pub sandbox_verdicts: Option<Value>,
+    pub jarm: Option<Lenient<String>>,
+    pub tags: Option<Lenient<Vec<Lenient<String>>>>,
+    pub whois: Option<Lenient<String>>,
+    pub last_dns_records: Option<Lenient<Vec<Lenient<DnsRecordAttributes>>>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PopularThreatClassification {
+    pub suggested_threat_label: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnalysisResult {
+    pub category: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DnsRecordAttributes {
+    #[serde(rename = "type")]
+    pub record_type: String,
+    pub value: String,
+    #[serde(default)]
+    pub priority: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VTRelationship {
+    pub data: Option<RelationshipData>,
+}
+
+// To-many relationships carry a list of descriptors, to-one relationships
+// (e.g. last_serving_ip_address) a single descriptor or null.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum RelationshipData {
+    Many(Vec<Lenient<Descriptor>>),
+    One(Descriptor),
+    Other(Value),
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Descriptor {
+    pub id: String,
+    #[serde(rename = "type", default)]
+    pub object_type: String,
+    pub context_attributes: Option<Value>,
+    // Only present when the relationship objects were fetched in full (e.g.
+    // resolutions with their date).
+    pub attributes: Option<Value>,
+}
+
+impl VTRelationship {
+    pub fn into_descriptors(self, name: &str, warnings: &mut Vec<String>) -> Vec<Descriptor> {
+        match self.data {
+            None => Vec::new(),
+            Some(RelationshipData::Many(items)) => collect_valid(items, name, warnings),
+            Some(RelationshipData::One(descriptor)) => vec![descriptor],
+            Some(RelationshipData::Other(value)) => {
+                warnings.push(format!("{}: unexpected relationship data {}", name, preview(&value)));
+                Vec::new()
+            }
+        }
+    }
+}
+
+fn preview(value: &Value) -> String {
+    let text = value.to_string();
+    if text.chars().count() > 80 {
+        format!("{}...", text.chars().take(80).collect::<String>())
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_lenient_fields() {
+        let attrs: VTAttributes = serde_json::from_value(json!({
+            "tags": ["ok", 5],
+            "reputation": "high",
+            "last_dns_records": {"type": "A"}
+        }))
+        .unwrap();
+        let mut warnings = Vec::new();
+        let tags = collect_valid(attrs.tags.unwrap().into_valid("tags", &mut warnings).unwrap(), "tags", &mut warnings);
+        assert_eq!(tags, vec!["ok".to_string()]);
+        assert!(attrs.reputation.unwrap().into_valid("reputation", &mut warnings).is_none());
+        assert!(attrs.last_dns_records.unwrap().into_valid("last_dns_records", &mut warnings).is_none());
+        assert_eq!(warnings.len(), 3);
+    }
+
+    #[test]
+    fn test_relationship_shapes() {
+        let rels: HashMap<String, VTRelationship> = serde_json::from_value(json!({
+            "contacted_ips": {"data": [{"type": "ip_address", "id": "192.0.2.1"}, {"type": "ip_address"}]},
+            "last_serving_ip_address": {"data": {"type": "ip_address", "id": "192.0.2.2"}},
+            "network_location": {"data": null},
+            "graphs": {"links": {}}
+        }))
+        .unwrap();
+        let mut warnings = Vec::new();
+        let mut ids: Vec<String> = rels
+            .into_iter()
+            .flat_map(|(name, rel)| rel.into_descriptors(&name, &mut warnings))
+            .map(|d| d.id)
+            .collect();
+        ids.sort();
+        assert_eq!(ids, vec!["192.0.2.1", "192.0.2.2"]);
+        assert_eq!(warnings.len(), 1);
+    }
+}
@@ -1,27 +1,46 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fs::File;
 use std::io::Read;
 
+use crate::indicator::{normalize_domain, normalize_ip};
 use crate::input_type::is_file;
-use crate::types::{Node, Edge, Properties};
+use crate::types::{Graph, Node, Edge, Observation, Properties, Provenance};
+use crate::whois::{self, WhoisRecord};
 
 #[derive(Deserialize, Debug)]
 struct JsonInput {
     identity_and_verdict: IdentityAndVerdict,
     activity_and_relationships: Option<ActivityAndRelationships>,
+    #[serde(default)]
+    warnings: Vec<String>,
+    #[serde(default)]
+    retrieval: Option<Retrieval>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Retrieval {
+    endpoint: String,
+    retrieved_at: DateTime<Utc>,
 }
 
 #[derive(Deserialize, Debug)]
 struct IdentityAndVerdict {
     threat: Threat,
     whois: Option<HashMap<String, String>>,
+    #[serde(default)]
+    whois_record: Option<WhoisRecord>,
 }
 
 #[derive(Deserialize, Debug)]
 struct Threat {
     query: String,
+    #[serde(default)]
+    first_seen: Option<String>,
+    #[serde(default)]
+    last_seen: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -36,6 +55,20 @@ struct RelatedItems {
     contacted_ips: Option<Vec<String>>,
     contacted_domains: Option<Vec<String>>,
     resolves_to: Option<Vec<ResolveRelationship>>,
+    #[serde(default)]
+    related: Vec<RelatedObject>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RelatedObject {
+    relationship: String,
+    edge_type: String,
+    direction: String,
+    id: String,
+    #[serde(rename = "type")]
+    node_type: String,
+    #[serde(default)]
+    date: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -49,138 +82,248 @@ struct DnsRecord {
     #[serde(rename = "type")]
     record_type: String,
     value: String,
+    #[serde(default)]
+    priority: Option<i64>,
 }
 
 pub fn parse_vt_json(filename: &str) -> Result<(Vec<Node>, Vec<Edge>), Box<dyn Error>> {
-    let data: String;
-
-    if is_file(filename) {
-        let mut file = File::open(&filename)?;
+    let data = if is_file(filename) {
+        let mut file = File::open(filename)?;
         let mut file_data = String::new();
         file.read_to_string(&mut file_data)?;
-        data = file_data;
+        file_data
     } else { // direct data
-        data = filename.to_string();
-    }
+        filename.to_string()
+    };
     println!("Data: {}", &data);
     let json_input: JsonInput = serde_json::from_str(&data)?;
+    // Intermediate files written before lookups were stamped only say
+    // which file they came from.
+    let provenance = match &json_input.retrieval {
+        Some(retrieval) => Provenance::new("virustotal", Some(&retrieval.endpoint)).at(retrieval.retrieved_at),
+        None => Provenance::new("vt_json", is_file(filename).then_some(filename)),
+    };
 
-    let threat_query = json_input.identity_and_verdict.threat.query;
+    let threat = json_input.identity_and_verdict.threat;
+    let threat_query = threat.query;
     let mut nodes = Vec::new();
     let mut edges = Vec::new();
 
-    // Add the threat node with properties
+    // Add the threat node with properties. Older intermediate files only
+    // carry the flat whois map, so fall back to normalizing that.
+    let whois = json_input
+        .identity_and_verdict
+        .whois_record
+        .or_else(|| json_input.identity_and_verdict.whois.map(whois::from_fields));
     let mut threat_props = HashMap::new();
-    if let Some(whois) = &json_input.identity_and_verdict.whois {
-        for (key, value) in whois {
-            threat_props.insert(key.clone(), value.clone());
-        }
+    if let Some(whois) = &whois {
+        threat_props.extend(whois_properties(whois));
     }
+    if !json_input.warnings.is_empty() {
+        threat_props.insert("vt_warnings".to_string(), json_input.warnings.join("; "));
+    }
+    // VT submission dates bound when the indicator was seen in the wild.
     nodes.push(Node {
         id: Properties::get_id_from_str(&threat_query),
         label: threat_query.clone(),
         node_type: "threat".to_string(),
-        properties: Properties::map_values(threat_props)
+        properties: Properties::map_values(threat_props),
+        first_seen: threat.first_seen.as_deref().and_then(parse_time),
+        last_seen: threat.last_seen.as_deref().and_then(parse_time),
+        ..Default::default()
     });
 
-    if let Some(activity) = json_input.activity_and_relationships {
-        if let Some(communicating_files) = activity.related_items.communicating_files {
-            for file in communicating_files {
-                nodes.push(Node {
-                    id: Properties::get_id_from_str(&file),
-                    label: "Communicating File".to_string(),
-                    node_type: "file".to_string(),
-                    properties: Properties::new(),
-                });
-
-                edges.push(Edge {
-                    source: threat_query.clone(),
-                    target: file,
-                    relation_type: "communicates_with".to_string(),
-                    properties: Properties::new(),
-                });
-            }
+    let mut seen: HashSet<String> = HashSet::new();
+    seen.insert(threat_query.clone());
+    let mut push_node = |nodes: &mut Vec<Node>, value: &str, node_type: &str| {
+        if seen.insert(value.to_string()) {
+            nodes.push(Node {
+                id: Properties::get_id_from_str(value),
+                label: value.to_string(),
+                node_type: node_type.to_string(),
+                properties: Properties::new(),
+                ..Default::default()
+            });
         }
+    };
 
-        if let Some(contacted_ips) = activity.related_items.contacted_ips {
-            for ip in contacted_ips {
-                nodes.push(Node {
-                    id: Properties::get_id_from_str(&ip),
-                    label: "Contacted IP".to_string(),
-                    node_type: "ip".to_string(),
-                    properties: Properties::new(),
-                });
+    // WHOIS entities become nodes so shared registrars, registrant emails
+    // and name servers link domains together.
+    if let Some(whois) = &whois {
+        let mut entities: Vec<(&str, &str, &str)> = Vec::new();
+        if let Some(registrar) = &whois.registrar {
+            entities.push((registrar, "registrar", "registered_with"));
+        }
+        if let Some(email) = whois::registrant_email_entity(whois) {
+            entities.push((email, "email", "registrant_email"));
+        }
+        for server in &whois.name_servers {
+            entities.push((server, "domain", "has_ns"));
+        }
+        for (value, node_type, relation_type) in entities {
+            push_node(&mut nodes, value, node_type);
+            let mut properties = Properties::new();
+            properties.insert("source".to_string(), "whois".to_string());
+            edges.push(Edge {
+                source: threat_query.clone(),
+                target: value.to_string(),
+                relation_type: relation_type.to_string(),
+                properties,
+                ..Default::default()
+            });
+        }
+    }
 
-                edges.push(Edge {
-                    source: threat_query.clone(),
-                    target: ip,
-                    relation_type: "contacted".to_string(),
-                    properties: Properties::new(),
+    if let Some(activity) = json_input.activity_and_relationships {
+        let related_items = activity.related_items;
+        let mut related = related_items.related;
+        let legacy = [
+            (related_items.communicating_files, "communicating_files", "communicates_with", "in", "file"),
+            (related_items.contacted_ips, "contacted_ips", "contacted_ip", "out", "ip"),
+            (related_items.contacted_domains, "contacted_domains", "contacted_domain", "out", "domain"),
+        ];
+        for (ids, relationship, edge_type, direction, node_type) in legacy {
+            for id in ids.unwrap_or_default() {
+                related.push(RelatedObject {
+                    relationship: relationship.to_string(),
+                    edge_type: edge_type.to_string(),
+                    direction: direction.to_string(),
+                    id,
+                    node_type: node_type.to_string(),
+                    date: None,
                 });
             }
         }
 
-        if let Some(contacted_domains) = activity.related_items.contacted_domains {
-            for domain in contacted_domains {
-                nodes.push(Node {
-                    id: Properties::get_id_from_str(&domain),
-                    label: "Contacted Domain".to_string(),
-                    node_type: "domain".to_string(),
-                    properties: Properties::new(),
-                });
+        for item in related {
+            push_node(&mut nodes, &item.id, &item.node_type);
 
-                edges.push(Edge {
-                    source: threat_query.clone(),
-                    target: domain,
-                    relation_type: "contacted".to_string(),
-                    properties: Properties::new(),
-                });
+            let (source, target) = if item.direction == "in" {
+                (item.id, threat_query.clone())
+            } else {
+                (threat_query.clone(), item.id)
+            };
+            let mut properties = Properties::new();
+            properties.insert("vt_relationship".to_string(), item.relationship);
+            let mut edge = Edge {
+                source,
+                target,
+                relation_type: item.edge_type,
+                properties,
+                ..Default::default()
+            };
+            if let Some(date) = item.date.as_deref().and_then(parse_time) {
+                edge.observe(&Observation::at(date));
             }
+            edges.push(edge);
         }
 
-        if let Some(resolutions) = activity.related_items.resolves_to {
+        if let Some(resolutions) = related_items.resolves_to {
             for resolution in resolutions {
-                nodes.push(Node {
-                    id: Properties::get_id_from_str(&resolution.domain),
-                    label: "Resolved Domain".to_string(),
-                    node_type: "domain".to_string(),
-                    properties: Properties::new(),
-                });
-                nodes.push(Node {
-                    id: Properties::get_id_from_str(&resolution.ip),
-                    label: "Resolved IP".to_string(),
-                    node_type: "ip".to_string(),
-                    properties: Properties::new(),
-                });
+                push_node(&mut nodes, &resolution.domain, "domain");
+                push_node(&mut nodes, &resolution.ip, "ip");
                 edges.push(Edge {
                     source: resolution.domain.clone(),
                     target: resolution.ip.clone(),
                     relation_type: "resolves_to".to_string(),
                     properties: Properties::new(),
+                    ..Default::default()
                 });
             }
         }
 
         if let Some(dns_records) = activity.dns {
+            let mut dns_props: HashMap<String, Vec<String>> = HashMap::new();
             for dns_record in dns_records {
-                nodes.push(Node {
-                    id: Properties::get_id_from_str(&dns_record.value),
-                    label: dns_record.record_type.clone(),
-                    node_type: "dns".to_string(),
-                    properties: Properties::new(),
+                let record_type = dns_record.record_type.to_uppercase();
+                let target = dns_record_edge(&record_type).and_then(|(node_type, relation_type)| {
+                    let value = if node_type == "ip" {
+                        normalize_ip(&dns_record.value)
+                    } else {
+                        normalize_domain(&dns_record.value)
+                    };
+                    value.map(|value| (value, node_type, relation_type))
                 });
 
+                let Some((value, node_type, relation_type)) = target else {
+                    dns_props
+                        .entry(format!("dns_{}", record_type.to_lowercase()))
+                        .or_default()
+                        .push(dns_record.value);
+                    continue;
+                };
+
+                push_node(&mut nodes, &value, node_type);
+                // WHOIS may already have linked the same name server.
+                if edges.iter().any(|e| e.source == threat_query && e.target == value && e.relation_type == relation_type) {
+                    continue;
+                }
+                let mut properties = Properties::new();
+                properties.insert("source".to_string(), "dns".to_string());
+                if let Some(priority) = dns_record.priority {
+                    properties.insert("priority".to_string(), priority.to_string());
+                }
                 edges.push(Edge {
                     source: threat_query.clone(),
-                    target: dns_record.value.clone(),
-                    relation_type: "has_dns".to_string(),
-                    properties: Properties::new(),
+                    target: value,
+                    relation_type: relation_type.to_string(),
+                    properties,
+                    ..Default::default()
                 });
             }
+            for (key, values) in dns_props {
+                nodes[0].properties.insert(key, values.join(" | "));
+            }
         }
     }
 
-    Ok((nodes, edges))
+    let mut graph = Graph { nodes, edges, ..Default::default() };
+    graph.attribute(&provenance);
+    Ok((graph.nodes, graph.edges))
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value).ok().map(|t| t.with_timezone(&Utc))
+}
+
+// Node and edge type for DNS records that point at other infrastructure.
+// Everything else (TXT, SOA, CAA, ...) is kept as a property of the queried
+// node.
+pub(crate) fn dns_record_edge(record_type: &str) -> Option<(&'static str, &'static str)> {
+    match record_type {
+        "A" | "AAAA" => Some(("ip", "resolves_to")),
+        "NS" => Some(("domain", "has_ns")),
+        "MX" => Some(("domain", "has_mx")),
+        "CNAME" => Some(("domain", "cname_of")),
+        _ => None,
+    }
+}
+
+fn whois_properties(whois: &WhoisRecord) -> HashMap<String, String> {
+    let fields = [
+        ("registrar", &whois.registrar),
+        ("registrar_iana_id", &whois.registrar_iana_id),
+        ("whois_created", &whois.creation_date),
+        ("whois_updated", &whois.updated_date),
+        ("whois_expires", &whois.expiry_date),
+        ("registrant_name", &whois.registrant_name),
+        ("registrant_org", &whois.registrant_org),
+        ("registrant_email", &whois.registrant_email),
+        ("registrant_country", &whois.registrant_country),
+        ("abuse_email", &whois.abuse_email),
+        ("privacy_service", &whois.privacy_service),
+    ];
+    let mut properties: HashMap<String, String> = fields
+        .into_iter()
+        .filter_map(|(key, value)| value.clone().map(|v| (key.to_string(), v)))
+        .collect();
+    if !whois.name_servers.is_empty() {
+        properties.insert("name_servers".to_string(), whois.name_servers.join(","));
+    }
+    if whois.privacy {
+        properties.insert("whois_privacy".to_string(), "true".to_string());
+    }
+    properties
 }
 
 #[cfg(test)]
@@ -190,6 +333,28 @@ mod tests {
     #[test]
     fn test_parse_vt_json() {
         let result = parse_vt_json("example_data/vt.json");
-        assert_eq!(result.is_ok(), false);
+        assert!(result.is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_vt_json_related_objects() {
+        let data = r#"{
+            "identity_and_verdict": {"threat": {"query": "example.com"}, "whois": null},
+            "activity_and_relationships": {
+                "related_items": {
+                    "contacted_ips": ["192.0.2.1"],
+                    "related": [
+                        {"relationship": "resolutions", "edge_type": "resolves_to", "direction": "out", "id": "192.0.2.1", "type": "ip"},
+                        {"relationship": "communicating_files", "edge_type": "communicates_with", "direction": "in", "id": "abc123", "type": "file"}
+                    ]
+                },
+                "dns": null
+            }
+        }"#;
+        let (nodes, edges) = parse_vt_json(data).unwrap();
+        assert_eq!(nodes.len(), 3);
+        assert!(edges.iter().any(|e| e.source == "abc123" && e.target == "example.com"));
+        assert!(edges.iter().any(|e| e.relation_type == "resolves_to" && e.target == "192.0.2.1"));
+        assert!(edges.iter().any(|e| e.relation_type == "contacted_ip"));
+    }
+}
@@ -0,0 +1,198 @@
+// Catalogue of VirusTotal v3 relationships per object collection and the
+// edge each one becomes in the universal graph.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    // queried object -> related object
+    Outgoing,
+    // related object -> queried object
+    Incoming,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Outgoing => "out",
+            Direction::Incoming => "in",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RelationshipSpec {
+    pub name: &'static str,
+    pub edge_type: &'static str,
+    pub direction: Direction,
+    pub default: bool,
+}
+
+const fn rel(name: &'static str, edge_type: &'static str, direction: Direction, default: bool) -> RelationshipSpec {
+    RelationshipSpec { name, edge_type, direction, default }
+}
+
+use Direction::{Incoming, Outgoing};
+
+const FILES: &[RelationshipSpec] = &[
+    rel("collections", "member_of", Outgoing, true),
+    rel("contacted_ips", "contacted_ip", Outgoing, true),
+    rel("contacted_domains", "contacted_domain", Outgoing, true),
+    rel("contacted_urls", "contacted_url", Outgoing, true),
+    rel("itw_ips", "downloaded_from_ip", Outgoing, true),
+    rel("itw_domains", "downloaded_from_domain", Outgoing, true),
+    rel("itw_urls", "downloaded_from_url", Outgoing, true),
+    rel("dropped_files", "dropped", Outgoing, false),
+    rel("execution_parents", "executed", Incoming, false),
+    rel("bundled_files", "bundles", Outgoing, false),
+    rel("compressed_parents", "compresses", Incoming, false),
+    rel("embedded_domains", "embeds_domain", Outgoing, false),
+    rel("embedded_ips", "embeds_ip", Outgoing, false),
+    rel("embedded_urls", "embeds_url", Outgoing, false),
+    rel("email_attachments", "has_attachment", Outgoing, false),
+    rel("email_parents", "attached_to", Outgoing, false),
+    rel("overlay_children", "has_overlay", Outgoing, false),
+    rel("overlay_parents", "overlay_of", Outgoing, false),
+    rel("pcap_children", "pcap_contains", Outgoing, false),
+    rel("pcap_parents", "captured_in", Outgoing, false),
+    rel("pe_resource_children", "has_pe_resource", Outgoing, false),
+    rel("pe_resource_parents", "pe_resource_of", Outgoing, false),
+    rel("similar_files", "similar_to", Outgoing, false),
+    rel("related_threat_actors", "attributed_to", Outgoing, false),
+];
+
+const DOMAINS: &[RelationshipSpec] = &[
+    rel("collections", "member_of", Outgoing, true),
+    rel("communicating_files", "communicates_with", Incoming, true),
+    rel("resolutions", "resolves_to", Outgoing, true),
+    rel("referrer_files", "refers_to", Incoming, true),
+    rel("downloaded_files", "served_file", Outgoing, true),
+    rel("subdomains", "has_subdomain", Outgoing, false),
+    rel("parent", "has_parent_domain", Outgoing, false),
+    rel("immediate_parent", "has_immediate_parent", Outgoing, false),
+    rel("siblings", "sibling_of", Outgoing, false),
+    rel("historical_ssl_certificates", "had_certificate", Outgoing, false),
+    rel("historical_whois", "had_whois", Outgoing, false),
+    rel("urls", "hosts_url", Outgoing, false),
+    rel("cname_records", "has_cname_record", Outgoing, false),
+    rel("mx_records", "has_mx_record", Outgoing, false),
+    rel("ns_records", "has_ns_record", Outgoing, false),
+    rel("soa_records", "has_soa_record", Outgoing, false),
+    rel("caa_records", "has_caa_record", Outgoing, false),
+    rel("related_threat_actors", "attributed_to", Outgoing, false),
+];
+
+const IP_ADDRESSES: &[RelationshipSpec] = &[
+    rel("collections", "member_of", Outgoing, true),
+    rel("communicating_files", "communicates_with", Incoming, true),
+    rel("resolutions", "resolves_to", Incoming, true),
+    rel("referrer_files", "refers_to", Incoming, true),
+    rel("downloaded_files", "served_file", Outgoing, true),
+    rel("historical_ssl_certificates", "had_certificate", Outgoing, false),
+    rel("historical_whois", "had_whois", Outgoing, false),
+    rel("urls", "hosts_url", Outgoing, false),
+    rel("related_threat_actors", "attributed_to", Outgoing, false),
+];
+
+const URLS: &[RelationshipSpec] = &[
+    rel("collections", "member_of", Outgoing, true),
+    rel("communicating_files", "communicates_with", Incoming, true),
+    rel("referrer_files", "refers_to", Incoming, true),
+    rel("downloaded_files", "served_file", Outgoing, true),
+    rel("contacted_domains", "contacted_domain", Outgoing, false),
+    rel("contacted_ips", "contacted_ip", Outgoing, false),
+    rel("last_serving_ip_address", "served_from", Outgoing, false),
+    rel("network_location", "located_at", Outgoing, false),
+    rel("redirecting_urls", "redirects_to", Incoming, false),
+    rel("redirects_to", "redirects_to", Outgoing, false),
+    rel("referrer_urls", "links_to", Incoming, false),
+    rel("related_threat_actors", "attributed_to", Outgoing, false),
+];
+
+pub fn catalogue(item_type: &str) -> &'static [RelationshipSpec] {
+    match item_type {
+        "files" => FILES,
+        "domains" => DOMAINS,
+        "ip_addresses" => IP_ADDRESSES,
+        "urls" => URLS,
+        _ => &[],
+    }
+}
+
+pub fn find(item_type: &str, name: &str) -> Option<&'static RelationshipSpec> {
+    catalogue(item_type).iter().find(|spec| spec.name == name)
+}
+
+// Relationship names to request for `item_type`. `None` gives the defaults,
+// "all" the whole catalogue; names the object type does not support are
+// skipped so one selection can be used for a mixed batch of indicators.
+pub fn select(item_type: &str, selection: Option<&[String]>) -> Vec<&'static str> {
+    let specs = catalogue(item_type);
+    match selection {
+        None => specs.iter().filter(|s| s.default).map(|s| s.name).collect(),
+        Some(names) if names.iter().any(|n| n == "all") => specs.iter().map(|s| s.name).collect(),
+        Some(names) => specs
+            .iter()
+            .filter(|s| names.iter().any(|n| n == s.name))
+            .map(|s| s.name)
+            .collect(),
+    }
+}
+
+pub fn unknown_relationships(selection: &[String]) -> Vec<String> {
+    selection
+        .iter()
+        .filter(|name| *name != "all")
+        .filter(|name| {
+            ["files", "domains", "ip_addresses", "urls"]
+                .iter()
+                .all(|item_type| find(item_type, name).is_none())
+        })
+        .cloned()
+        .collect()
+}
+
+// Graph node type for a VT object type as found in relationship descriptors.
+pub fn node_type_for(object_type: &str) -> &str {
+    match object_type {
+        "file" => "file",
+        "ip_address" => "ip",
+        "domain" => "domain",
+        "url" => "url",
+        "collection" => "collection",
+        "ssl_cert" => "ssl_certificate",
+        "whois" => "whois",
+        "threat_actor" => "threat_actor",
+        "" => "unknown",
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edge_types_are_distinct_per_object_type() {
+        for item_type in ["files", "domains", "ip_addresses", "urls"] {
+            let specs = catalogue(item_type);
+            for (i, spec) in specs.iter().enumerate() {
+                assert!(specs[i + 1..].iter().all(|o| o.name != spec.name));
+                assert!(specs[i + 1..]
+                    .iter()
+                    .all(|o| o.edge_type != spec.edge_type || o.direction != spec.direction));
+            }
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let defaults = select("domains", None);
+        assert_eq!(defaults.iter().filter(|n| **n == "resolutions").count(), 1);
+        assert!(!defaults.contains(&"subdomains"));
+
+        let chosen = vec!["dropped_files".to_string(), "subdomains".to_string()];
+        assert_eq!(select("files", Some(&chosen)), vec!["dropped_files"]);
+        assert_eq!(select("domains", Some(&chosen)), vec!["subdomains"]);
+        assert_eq!(select("urls", Some(&["all".to_string()])).len(), URLS.len());
+        assert_eq!(unknown_relationships(&["bogus".to_string(), "siblings".to_string()]), vec!["bogus"]);
+    }
+}
@@ -0,0 +1,227 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::indicator::normalize_domain;
+
+// Normalized view of a WHOIS record. Registries disagree on field names and
+// date formats, so each field is looked up under the spellings seen in the
+// wild and dates are turned into RFC 3339 where they can be parsed.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WhoisRecord {
+    pub registrar: Option<String>,
+    pub registrar_iana_id: Option<String>,
+    pub creation_date: Option<String>,
+    pub updated_date: Option<String>,
+    pub expiry_date: Option<String>,
+    pub registrant_name: Option<String>,
+    pub registrant_org: Option<String>,
+    pub registrant_email: Option<String>,
+    pub registrant_country: Option<String>,
+    pub abuse_email: Option<String>,
+    #[serde(default)]
+    pub name_servers: Vec<String>,
+    #[serde(default)]
+    pub privacy: bool,
+    pub privacy_service: Option<String>,
+}
+
+const REGISTRAR: &[&str] = &["registrar", "sponsoring registrar", "registrar name", "registrar organization"];
+const REGISTRAR_IANA_ID: &[&str] = &["registrar iana id", "sponsoring registrar iana id"];
+const CREATED: &[&str] = &[
+    "creation date",
+    "created",
+    "created on",
+    "created date",
+    "registered on",
+    "registration time",
+    "domain registration date",
+];
+const UPDATED: &[&str] = &["updated date", "last updated", "last updated on", "last-modified", "changed", "modified"];
+const EXPIRES: &[&str] = &[
+    "registry expiry date",
+    "registrar registration expiration date",
+    "expiration date",
+    "expiry date",
+    "expires",
+    "expires on",
+    "paid-till",
+    "expiration time",
+];
+const REGISTRANT_NAME: &[&str] = &["registrant name", "registrant", "person"];
+const REGISTRANT_ORG: &[&str] = &["registrant organization", "registrant organisation", "registrant org", "org"];
+const REGISTRANT_EMAIL: &[&str] = &["registrant email", "registrant e-mail", "registrant contact email", "e-mail"];
+const REGISTRANT_COUNTRY: &[&str] = &["registrant country", "registrant country/economy", "country"];
+const ABUSE_EMAIL: &[&str] = &["registrar abuse contact email", "abuse contact email"];
+const NAME_SERVERS: &[&str] = &["name server", "nameserver", "nameservers", "name servers", "nserver"];
+
+// Values that mean the real registrant is hidden behind a proxy or redacted.
+const PRIVACY_MARKERS: &[&str] = &[
+    "privacy",
+    "redacted",
+    "withheld",
+    "whoisguard",
+    "domains by proxy",
+    "perfect privacy",
+    "gdpr masked",
+    "data protected",
+    "not disclosed",
+    "identity protect",
+    "proxy",
+];
+
+pub fn parse_whois(raw: &str) -> WhoisRecord {
+    let fields = raw.lines().filter_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        Some((key.to_string(), value.to_string()))
+    });
+    from_fields(fields)
+}
+
+// Builds a record from already split key/value pairs, e.g. the flat whois map
+// of older intermediate JSON files.
+pub fn from_fields<I: IntoIterator<Item = (String, String)>>(fields: I) -> WhoisRecord {
+    let mut values: HashMap<String, Vec<String>> = HashMap::new();
+    for (key, value) in fields {
+        let value = value.trim();
+        if value.is_empty() {
+            continue;
+        }
+        values
+            .entry(key.trim().to_lowercase())
+            .or_default()
+            .push(value.to_string());
+    }
+
+    let first = |keys: &[&str]| keys.iter().find_map(|k| values.get(*k).and_then(|v| v.first()).cloned());
+    let date = |keys: &[&str]| first(keys).map(|d| normalize_date(&d));
+
+    let mut name_servers: Vec<String> = Vec::new();
+    for key in NAME_SERVERS {
+        for value in values.get(*key).into_iter().flatten() {
+            // Some registries list several servers (or "ns1 192.0.2.1") per line.
+            let servers = value
+                .split([',', ' '])
+                .filter(|s| s.contains('.') && s.parse::<std::net::IpAddr>().is_err())
+                .filter_map(normalize_domain);
+            for server in servers {
+                if !name_servers.contains(&server) {
+                    name_servers.push(server);
+                }
+            }
+        }
+    }
+
+    let mut record = WhoisRecord {
+        registrar: first(REGISTRAR),
+        registrar_iana_id: first(REGISTRAR_IANA_ID),
+        creation_date: date(CREATED),
+        updated_date: date(UPDATED),
+        expiry_date: date(EXPIRES),
+        registrant_name: first(REGISTRANT_NAME),
+        registrant_org: first(REGISTRANT_ORG),
+        registrant_email: first(REGISTRANT_EMAIL).map(|e| e.to_lowercase()),
+        registrant_country: first(REGISTRANT_COUNTRY),
+        abuse_email: first(ABUSE_EMAIL).map(|e| e.to_lowercase()),
+        name_servers,
+        privacy: false,
+        privacy_service: None,
+    };
+
+    for value in [&record.registrant_org, &record.registrant_name, &record.registrant_email]
+        .into_iter()
+        .flatten()
+    {
+        if is_privacy_marker(value) {
+            record.privacy = true;
+            if record.privacy_service.is_none() && !value.to_lowercase().contains("redacted") {
+                record.privacy_service = Some(value.clone());
+            }
+        }
+    }
+    record
+}
+
+pub fn is_privacy_marker(value: &str) -> bool {
+    let value = value.to_lowercase();
+    PRIVACY_MARKERS.iter().any(|marker| value.contains(marker))
+}
+
+// Registrant email worth turning into a node: a real address that is not a
+// proxy service mailbox shared by thousands of unrelated domains.
+pub fn registrant_email_entity(record: &WhoisRecord) -> Option<&str> {
+    record
+        .registrant_email
+        .as_deref()
+        .filter(|email| email.contains('@') && !is_privacy_marker(email))
+}
+
+fn normalize_date(value: &str) -> String {
+    let value = value.trim();
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return datetime.with_timezone(&Utc).format("%Y-%m-%dT%H:%M:%SZ").to_string();
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M:%S%.f"] {
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), format) {
+            return datetime.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+        }
+    }
+    // Drop a trailing timezone name such as "UTC" before trying date formats.
+    let date_part = value.split_whitespace().next().unwrap_or(value);
+    for format in ["%Y-%m-%d", "%Y.%m.%d", "%Y/%m/%d", "%d-%b-%Y", "%d.%m.%Y", "%d/%m/%Y"] {
+        if let Ok(date) = NaiveDate::parse_from_str(date_part, format) {
+            return format!("{}T00:00:00Z", date.format("%Y-%m-%d"));
+        }
+    }
+    value.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_whois_icann_format() {
+        let raw = "Domain Name: EXAMPLE.COM\n\
+                   Creation Date: 1995-08-14T04:00:00Z\n\
+                   Registry Expiry Date: 2025-08-13T04:00:00+02:00\n\
+                   Registrar: RESERVED-Internet Assigned Numbers Authority\n\
+                   Registrar IANA ID: 376\n\
+                   Registrant Organization: Internet Assigned Numbers Authority\n\
+                   Registrant Email: Domain-Admin@iana.org\n\
+                   Name Server: A.IANA-SERVERS.NET\n\
+                   Name Server: B.IANA-SERVERS.NET\n";
+        let record = parse_whois(raw);
+        assert_eq!(record.registrar.as_deref(), Some("RESERVED-Internet Assigned Numbers Authority"));
+        assert_eq!(record.registrar_iana_id.as_deref(), Some("376"));
+        assert_eq!(record.creation_date.as_deref(), Some("1995-08-14T04:00:00Z"));
+        assert_eq!(record.expiry_date.as_deref(), Some("2025-08-13T02:00:00Z"));
+        assert_eq!(record.registrant_email.as_deref(), Some("domain-admin@iana.org"));
+        assert_eq!(record.name_servers, vec!["a.iana-servers.net", "b.iana-servers.net"]);
+        assert!(!record.privacy);
+        assert_eq!(registrant_email_entity(&record), Some("domain-admin@iana.org"));
+    }
+
+    #[test]
+    fn test_parse_whois_other_formats_and_privacy() {
+        let raw = "domain:        EXAMPLE.RU\n\
+                   nserver:       ns1.example.ru. 192.0.2.53\n\
+                   nserver:       ns2.example.ru.\n\
+                   org:           Privacy Protect, LLC (PrivacyProtect.org)\n\
+                   created:       2004.06.22\n\
+                   paid-till:     25-Jun-2025\n\
+                   e-mail:        contact@privacyprotect.org\n";
+        let record = parse_whois(raw);
+        assert_eq!(record.name_servers, vec!["ns1.example.ru", "ns2.example.ru"]);
+        assert_eq!(record.creation_date.as_deref(), Some("2004-06-22T00:00:00Z"));
+        assert_eq!(record.expiry_date.as_deref(), Some("2025-06-25T00:00:00Z"));
+        assert!(record.privacy);
+        assert_eq!(record.privacy_service.as_deref(), Some("Privacy Protect, LLC (PrivacyProtect.org)"));
+        assert_eq!(registrant_email_entity(&record), None);
+
+        let redacted = parse_whois("Registrant Name: REDACTED FOR PRIVACY\nRegistrant Email: Please query the RDDS service\n");
+        assert!(redacted.privacy);
+        assert_eq!(redacted.privacy_service, None);
+        assert_eq!(registrant_email_entity(&redacted), None);
+    }
+}
@@ -0,0 +1,48 @@
+use axum::extract::Multipart;
+use axum::http::StatusCode;
+use universal_graph_transformer::graph_enricher;
+use crate::{get_time_stamp, stream_to_file, OUTPUT_DIRECTORY, UPLOADS_DIRECTORY};
+
+// Accepts an uploaded graph (GraphML/Maltego or graph JSON) in the `file`
+// field plus an optional `selection` field ("type:ip,type:domain" and/or
+// node labels), enriches the selected nodes through VirusTotal and returns
+// the path of the merged graph. Any other field is rejected.
+pub async fn handle_enrich(mut multipart: Multipart) -> Result<String, (StatusCode, String)> {
+    let my_uuid = get_time_stamp();
+    let file_name = my_uuid.to_string();
+    let output_path = format!("{}/{}.json", OUTPUT_DIRECTORY, my_uuid);
+    let mut selection = String::new();
+    let mut uploaded = false;
+
+    while let Ok(Some(field)) = multipart.next_field().await {
+        match field.name() {
+            Some("selection") => {
+                selection = field
+                    .text()
+                    .await
+                    .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+            }
+            Some("file") if !uploaded => {
+                stream_to_file(&file_name, field).await?;
+                uploaded = true;
+            }
+            Some("file") => return Err((StatusCode::BAD_REQUEST, "Only one graph file can be enriched".to_owned())),
+            name => {
+                let name = name.unwrap_or("without a name");
+                return Err((StatusCode::BAD_REQUEST, format!("Unexpected field {}", name)));
+            }
+        }
+    }
+    if !uploaded {
+        return Err((StatusCode::BAD_REQUEST, "No graph file uploaded".to_owned()));
+    }
+
+    let file_path = format!("{}/{}", UPLOADS_DIRECTORY, file_name);
+    match graph_enricher(&file_path, &selection, &output_path).await {
+        Ok(_) => Ok(output_path),
+        Err(err) => {
+            eprintln!("Enrichment error: {}", err);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, err))
+        }
+    }
+}
@@ -1,3 +1,4 @@
+pub mod enrich;
 pub mod text_submit;
 
 use axum::response::IntoResponse;
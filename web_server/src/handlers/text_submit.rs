@@ -1,15 +1,34 @@
 use axum::http::StatusCode;
 use axum::Json;
-use uuid::Uuid;
 use collector::{gptextract};
+use serde_json::Value;
+use universal_graph_transformer::graph_transformer_named;
+use universal_graph_transformer::indicator::{classify, IndicatorKind};
 use universal_graph_transformer::transform::save_json;
+use universal_graph_transformer::types::GraphMetadata;
 use crate::models::text_submit::TextSubmitInput;
 use crate::{get_time_stamp, OUTPUT_DIRECTORY};
 
 pub async fn handle_text_submit(payload: Json<TextSubmitInput>) -> Result<String, (StatusCode, String)> {
     println!("The text submitted is a URL: {}", payload.data);
     let input = payload.data.as_str();
-    let tags = vec!["h1", "h2", "h3", "h4", "p", "article", "td", "ul", "li", "lo"];
+
+    // A bare hash, IP or domain (defanged or not) is looked up on VirusTotal
+    // rather than sent to the LLM; URLs still go through page extraction.
+    let indicator = classify(input);
+    if indicator.kind != IndicatorKind::Url && indicator.kind.vt_collection().is_some() {
+        let output_path = format!("{}/{}.json", OUTPUT_DIRECTORY, get_time_stamp());
+        return match graph_transformer_named(&indicator.value, "vtapi", &output_path, &indicator.value).await {
+            Ok(_) => Ok(output_path),
+            Err(err) => {
+                eprintln!("VT lookup error: {}", err);
+                Err((StatusCode::INTERNAL_SERVER_ERROR, err))
+            }
+        };
+    }
+    // Defanged URLs ("hxxp://evil[.]com/a") are fetched in refanged form.
+    let is_url = indicator.kind == IndicatorKind::Url;
+    let input = if is_url { indicator.value.as_str() } else { input };
     let proxy_url: Option<&str> = None;
     let entities: Vec<&str>;
 
@@ -30,13 +49,26 @@ pub async fn handle_text_submit(payload: Json<TextSubmitInput>) -> Result<String
         }
     };
 
+    let mut graph: Value = match serde_json::from_str(&data) {
+        Ok(Value::Object(graph)) => Value::Object(graph),
+        _ => return Err((StatusCode::BAD_GATEWAY, "Extraction did not return a JSON object".to_string())),
+    };
+    let reference = if is_url { input } else { "submitted text" };
+    gptextract::attribute_extraction(&mut graph, reference);
+
+    let name = if is_url { input.to_string() } else { input.chars().take(60).collect() };
+    let mut metadata = GraphMetadata::new(&name, vec![reference.to_string()]);
+    metadata.description = Some("LLM extraction submitted through the web server".to_string());
+    metadata.tool_version = Some(concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")).to_string());
+    graph["metadata"] = serde_json::to_value(&metadata).unwrap_or(Value::Null);
+
     let my_uuid = get_time_stamp();
     let output_path = format!("{}/{}.json", OUTPUT_DIRECTORY, my_uuid);
 
-    match save_json(&output_path, &data) {
+    match save_json(&graph, &output_path) {
         Ok(_) => println!("Data saved to {}", output_path),
         Err(err) => eprintln!("Error saving data: {}", err)
     };
 
-    return Ok(output_path);
+    Ok(output_path)
 }
@@ -1,23 +1,20 @@
 extern crate dotenv;
 
 use dotenv::dotenv;
-use universal_graph_transformer::{graph_transformer, types::Graph};
+use universal_graph_transformer::graph_transformer_named;
 
 mod template;
 mod models;
 mod handlers;
 
-use uuid::Uuid;
 use axum::{
     body::Bytes,
     extract::{Multipart},
     http::StatusCode,
-    response::{IntoResponse, Json},
     routing::{get, post},
     BoxError, Router,
 };
 
-use tower::ServiceExt;
 use tower_http::{
     services::{ServeDir}
 };
@@ -28,15 +25,12 @@ use tokio::{fs::File, io::BufWriter};
 use tokio_util::io::StreamReader;
 use tokio::net::TcpListener;
 use crate::handlers::index;
+use crate::handlers::enrich::handle_enrich;
 use crate::handlers::text_submit::handle_text_submit;
 
 const UPLOADS_DIRECTORY: &str = "uploads";
 const OUTPUT_DIRECTORY: &str = "outputs";
 
-fn routes_static() -> Router {
-    Router::new().nest_service("/", get_service(ServeDir::new("./")))
-}
-
 #[tokio::main]
 async fn main() -> io::Result<()>{
     dotenv().ok();
@@ -45,7 +39,8 @@ async fn main() -> io::Result<()>{
         .route("/", get(index))
         .route("/upload", post(accept_form))
         .route("/text-submit", post(handle_text_submit))
-        .nest_service(format!("/{}", OUTPUT_DIRECTORY.clone()).as_str(), get_service(ServeDir::new(OUTPUT_DIRECTORY.to_owned())));
+        .route("/enrich", post(handle_enrich))
+        .nest_service(format!("/{}", OUTPUT_DIRECTORY).as_str(), get_service(ServeDir::new(OUTPUT_DIRECTORY)));
 
     let listener = TcpListener::bind("0.0.0.0:3000").await?;
     println!("listening on {}", listener.local_addr().unwrap());
@@ -63,9 +58,10 @@ async fn accept_form(mut multipart: Multipart) -> Result<String, (StatusCode, St
     while let Ok(Some(field)) = multipart.next_field().await {
         // Generate a random UUID
         let file_name = my_uuid.to_string();
+        let upload_name = field.file_name().unwrap_or(&file_name).to_string();
         file_path = format!("{}/{}", file_path, file_name);
         stream_to_file(&file_name, field).await?;
-        match graph_transformer(&file_path, "auto", &output_path).await {
+        match graph_transformer_named(&file_path, "auto", &output_path, &upload_name).await {
             Ok(_) => println!("Graph transformation successful"),
             Err(e) => eprintln!("An error occurred: {}", e),
         };
@@ -92,7 +88,7 @@ where
 
     async {
         // Convert the stream into an `AsyncRead`.
-        let body_with_io_error = stream.map_err(|err| io::Error::new(io::ErrorKind::Other, err));
+        let body_with_io_error = stream.map_err(|err| io::Error::other(err));
         let body_reader = StreamReader::new(body_with_io_error);
         futures::pin_mut!(body_reader);
 
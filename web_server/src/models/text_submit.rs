@@ -4,5 +4,4 @@ use serde::Deserialize;
 pub struct TextSubmitInput {
     pub data: String,
     pub entities: Option<String>,
-    pub isUrl: bool,
 }
\ No newline at end of file